@@ -0,0 +1,408 @@
+use std::{
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::files::write_atomic;
+use crate::map_data::Mode;
+
+// the catalog is looked for under these names, in order
+static MAP_FILES: [&str; 4] = [
+    "all_maps.json",
+    "all_maps.toml",
+    "all_maps.yaml",
+    "all_maps.yml",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl CatalogFormat {
+    pub fn from_path(path: &str) -> Result<Self, CatalogError> {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        match &*ext.to_lowercase() {
+            "json" => Ok(CatalogFormat::Json),
+            "toml" => Ok(CatalogFormat::Toml),
+            "yaml" | "yml" => Ok(CatalogFormat::Yaml),
+            _ => Err(CatalogError::UnknownFormat(path.to_string())),
+        }
+    }
+}
+
+/// TOML files can't be a bare list, so the groups are written as `[[group]]` tables
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlCatalog {
+    group: Vec<GroupEntry>,
+}
+
+/// The map catalog as written in `all_maps.json`, a list of groups in file order
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct Catalog {
+    pub groups: Vec<GroupEntry>,
+}
+
+/// A map and all of its variants
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GroupEntry {
+    /// basename shared by the variants
+    pub name: String,
+    pub gid: u16,
+    /// free-form descriptions like theme, visual style or size
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// gids of groups that look or play alike, the link goes both ways
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub similar: Vec<u16>,
+    /// ids of removed variants, plays of them in the log are skipped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retired: Vec<u16>,
+    pub variants: Vec<VariantEntry>,
+}
+
+/// One playable mode of a map
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VariantEntry {
+    /// the id written to the play log, unique across the catalog
+    pub id: u16,
+    pub mode: Mode,
+    /// the most players the variant holds
+    pub players: u16,
+    /// shown instead of the group name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub gag: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub disabled: bool,
+    /// multiplier on the map's score, 0 keeps it from being offered
+    #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")]
+    pub weight: f64,
+    /// free-form descriptions of this variant, on top of its group's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// old ids this variant replaced, plays logged under them count for it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<u16>,
+}
+
+impl std::fmt::Display for VariantEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        inline(self).fmt(f)
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn is_default_weight(w: &f64) -> bool {
+    *w == default_weight()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CatalogError {
+    #[error("{0}: {1}")]
+    Parse(String, String),
+    #[error("{0}: catalogs must end in .json, .toml, .yaml or .yml")]
+    UnknownFormat(String),
+    #[error("group {0}: name must not be empty")]
+    EmptyName(u16),
+    #[error("group {0}: tags must not be empty")]
+    EmptyTag(u16),
+    #[error("map {0}: nickname must not be empty")]
+    EmptyNickname(u16),
+    #[error("map {0}: weight must be a finite, non-negative number, not {1}")]
+    BadWeight(u16, f64),
+    #[error("Duplicate group gid {0}")]
+    DuplicateGid(u16),
+    #[error("Duplicate map id {0}")]
+    DuplicateId(u16),
+    #[error("Map id {0} is used more than once as a live, alias or retired id")]
+    ReusedId(u16),
+    #[error("group {0}: similar to unknown gid {1}")]
+    UnknownSimilar(u16, u16),
+}
+
+/// Writes JSON on one line with a space after every ':' and ',' and inside braces
+struct InlineFormatter;
+
+impl serde_json::ser::Formatter for InlineFormatter {
+    fn begin_object_key<W: ?Sized + Write>(&mut self, w: &mut W, first: bool) -> io::Result<()> {
+        w.write_all(if first { b" " } else { b", " })
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        w.write_all(b": ")
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        w.write_all(b" }")
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(&mut self, w: &mut W, first: bool) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            w.write_all(b", ")
+        }
+    }
+}
+
+fn inline<T: Serialize>(value: &T) -> String {
+    let mut out = Vec::new();
+    let mut ser = serde_json::Serializer::with_formatter(&mut out, InlineFormatter);
+    value.serialize(&mut ser).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+impl Catalog {
+    /// The first catalog file found in the working directory
+    pub fn default_path() -> String {
+        MAP_FILES
+            .iter()
+            .find(|f| Path::new(f).exists())
+            .unwrap_or(&MAP_FILES[0])
+            .to_string()
+    }
+
+    /// Load a catalog, the format is picked by extension
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let format = CatalogFormat::from_path(path)?;
+        let raw = fs::read_to_string(path)?;
+        let parse_err = |e: String| CatalogError::Parse(path.to_string(), e);
+
+        let catalog = match format {
+            CatalogFormat::Json => {
+                serde_json::from_str(&raw).map_err(|e| parse_err(e.to_string()))?
+            }
+            CatalogFormat::Toml => {
+                let t: TomlCatalog = toml::from_str(&raw).map_err(|e| parse_err(e.to_string()))?;
+                Catalog { groups: t.group }
+            }
+            CatalogFormat::Yaml => {
+                serde_yaml::from_str(&raw).map_err(|e| parse_err(e.to_string()))?
+            }
+        };
+        Ok(catalog)
+    }
+
+    pub fn to_string(&self, format: CatalogFormat) -> Result<String, Box<dyn Error>> {
+        Ok(match format {
+            CatalogFormat::Json => self.to_json(),
+            CatalogFormat::Toml => toml::to_string(&TomlCatalog {
+                group: self.groups.clone(),
+            })?,
+            CatalogFormat::Yaml => serde_yaml::to_string(self)?,
+        })
+    }
+
+    /// Write the catalog in the format picked by the extension of `path`
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let format = CatalogFormat::from_path(path)?;
+        write_atomic(path, &self.to_string(format)?)?;
+        Ok(())
+    }
+
+    /// JSON laid out like the hand-written catalog, one group field and one variant per line
+    fn to_json(&self) -> String {
+        let groups: Vec<String> = self
+            .groups
+            .iter()
+            .map(|g| {
+                let mut fields = vec![
+                    format!("        \"name\": {}", inline(&g.name)),
+                    format!("        \"gid\": {}", g.gid),
+                ];
+                if !g.tags.is_empty() {
+                    fields.push(format!("        \"tags\": {}", inline(&g.tags)));
+                }
+                if !g.similar.is_empty() {
+                    fields.push(format!("        \"similar\": {}", inline(&g.similar)));
+                }
+                if !g.retired.is_empty() {
+                    fields.push(format!("        \"retired\": {}", inline(&g.retired)));
+                }
+                let variants: Vec<String> = g
+                    .variants
+                    .iter()
+                    .map(|v| format!("            {}", inline(v)))
+                    .collect();
+                if variants.is_empty() {
+                    fields.push("        \"variants\": []".to_string());
+                } else {
+                    fields.push(format!(
+                        "        \"variants\": [\n{}\n        ]",
+                        variants.join(",\n")
+                    ));
+                }
+                format!("{{\n{}\n    }}", fields.join(",\n"))
+            })
+            .collect();
+        format!("[\n    {}\n]\n", groups.join(","))
+    }
+
+    /// The JSON Schema of the catalog format, for editors to validate against
+    pub fn schema() -> String {
+        let mut schema = schemars::schema_for!(Catalog);
+        schema.schema.metadata().title = Some("Map catalog".to_string());
+        serde_json::to_string_pretty(&schema).unwrap()
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EditError {
+    #[error("No group with gid {0}")]
+    UnknownGroup(u16),
+    #[error("No map with id {0}")]
+    UnknownMap(u16),
+    #[error("Unknown field '{0}', expected nickname, players, mode, gag, disabled or weight")]
+    UnknownField(String),
+    #[error("Bad value for {0}: '{1}'")]
+    BadValue(String, String),
+    #[error("No {0} left past 65535")]
+    OutOfIds(&'static str),
+    #[error(transparent)]
+    Invalid(#[from] CatalogError),
+}
+
+fn parse_value<T: std::str::FromStr>(field: &str, value: &str) -> Result<T, EditError> {
+    value
+        .parse()
+        .map_err(|_| EditError::BadValue(field.to_string(), value.to_string()))
+}
+
+impl Catalog {
+    /// One past the highest gid in use
+    pub fn next_gid(&self) -> Result<u16, EditError> {
+        match self.groups.iter().map(|g| g.gid).max() {
+            Some(gid) => gid.checked_add(1).ok_or(EditError::OutOfIds("gids")),
+            None => Ok(0),
+        }
+    }
+
+    /// One past the highest map id ever used, so old log lines never point at a new map
+    pub fn next_id(&self) -> Result<u16, EditError> {
+        let live = self
+            .variants()
+            .flat_map(|v| v.aliases.iter().chain([&v.id]));
+        let retired = self.groups.iter().flat_map(|g| g.retired.iter());
+        match live.chain(retired).max() {
+            Some(id) => id.checked_add(1).ok_or(EditError::OutOfIds("map ids")),
+            None => Ok(0),
+        }
+    }
+
+    pub fn variants(&self) -> impl Iterator<Item = &VariantEntry> {
+        self.groups.iter().flat_map(|g| g.variants.iter())
+    }
+
+    pub fn group(&self, gid: u16) -> Option<&GroupEntry> {
+        self.groups.iter().find(|g| g.gid == gid)
+    }
+
+    fn variant_mut(&mut self, id: u16) -> Result<&mut VariantEntry, EditError> {
+        self.groups
+            .iter_mut()
+            .flat_map(|g| g.variants.iter_mut())
+            .find(|v| v.id == id)
+            .ok_or(EditError::UnknownMap(id))
+    }
+
+    /// Add an empty group under the next free gid, returning it
+    pub fn add_group(&mut self, name: &str) -> Result<u16, EditError> {
+        let gid = self.next_gid()?;
+        self.groups.push(GroupEntry {
+            name: name.to_string(),
+            gid,
+            tags: Vec::new(),
+            similar: Vec::new(),
+            retired: Vec::new(),
+            variants: Vec::new(),
+        });
+        Ok(gid)
+    }
+
+    /// Add a variant to a group under the next free id, returning it
+    pub fn add_variant(&mut self, gid: u16, mode: Mode, players: u16) -> Result<u16, EditError> {
+        let id = self.next_id()?;
+        let group = self
+            .groups
+            .iter_mut()
+            .find(|g| g.gid == gid)
+            .ok_or(EditError::UnknownGroup(gid))?;
+        group.variants.push(VariantEntry {
+            id,
+            mode,
+            players,
+            nickname: None,
+            gag: false,
+            disabled: false,
+            weight: default_weight(),
+            tags: Vec::new(),
+            aliases: Vec::new(),
+        });
+        Ok(id)
+    }
+
+    /// Set one field of a variant from its text form, an empty nickname goes back to the group name
+    pub fn set(&mut self, id: u16, field: &str, value: &str) -> Result<(), EditError> {
+        let v = self.variant_mut(id)?;
+        match field {
+            "nickname" => v.nickname = Some(value.to_string()).filter(|n| !n.is_empty()),
+            "players" => v.players = parse_value(field, value)?,
+            "mode" => {
+                v.mode = value
+                    .try_into()
+                    .map_err(|_| EditError::BadValue(field.to_string(), value.to_string()))?
+            }
+            "gag" => v.gag = parse_value(field, value)?,
+            "disabled" => v.disabled = parse_value(field, value)?,
+            "weight" => v.weight = parse_value(field, value)?,
+            _ => return Err(EditError::UnknownField(field.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Remove a variant, retiring its id and any aliases so the log still loads. The group stays
+    /// in place even if it has no variants left.
+    pub fn remove_variant(&mut self, id: u16) -> Result<VariantEntry, EditError> {
+        for g in &mut self.groups {
+            if let Some(pos) = g.variants.iter().position(|v| v.id == id) {
+                let v = g.variants.remove(pos);
+                g.retired.push(v.id);
+                g.retired.extend(&v.aliases);
+                return Ok(v);
+            }
+        }
+        Err(EditError::UnknownMap(id))
+    }
+
+    /// Have plays logged under `old` count for the variant `id`
+    pub fn add_alias(&mut self, id: u16, old: u16) -> Result<(), EditError> {
+        for g in &mut self.groups {
+            g.retired.retain(|r| *r != old);
+        }
+        self.variant_mut(id)?.aliases.push(old);
+        Ok(())
+    }
+}
@@ -29,6 +29,7 @@ where
         }
     }
 
+    #[allow(dead_code)]
     fn without_color(self) -> Self {
         Self::default_color()
     }
@@ -0,0 +1,155 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fs,
+    path::Path,
+};
+
+use crate::files::{lock_for, write_atomic};
+use crate::map_data::{Groups, Maps, Mode, RcMap};
+use crate::map_scoring::{build_scores, ScoringConfig, ScoringState};
+use crate::play_log::{
+    merge_logs, read_lines, read_log_lines, LogEntry, LogLine, Origin, ARCHIVE_FILE, LOG_FILE,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum CompactError {
+    #[error("Scores of {map} would change by {change:.3} points, keep more than {keep} plays")]
+    ScoresChanged {
+        map: String,
+        change: f64,
+        keep: usize,
+    },
+}
+
+/// What a compaction moved
+pub struct Compaction {
+    pub archived: usize,
+    pub kept: usize,
+    /// largest change in any map's chance of being offered, in percentage points
+    pub change: f64,
+}
+
+/// The largest difference in offered percentage between scoring two histories, over every mode
+/// and every player count a map of that mode is sized for.
+///
+/// Every map is checked, not only those the pool, tags and weights in effect let through, as
+/// those can change after compacting.
+fn score_change(
+    full: Vec<LogEntry>,
+    kept: Vec<LogEntry>,
+    all_maps: &[RcMap],
+    config: &ScoringConfig,
+) -> (f64, Option<RcMap>) {
+    let config = &ScoringConfig {
+        roster: config.roster.clone(),
+        per_player: config.per_player,
+        params: config.params,
+        ..Default::default()
+    };
+    let full = ScoringState::from_log(full, all_maps, &config.params);
+    let kept = ScoringState::from_log(kept, all_maps, &config.params);
+
+    let mut worst = (0.0, None);
+    for mode in Mode::ordered() {
+        let sizes: BTreeSet<u16> = all_maps
+            .iter()
+            .filter(|m| m.mode == mode)
+            .map(|m| m.players)
+            .collect();
+        for players in sizes {
+            let before: HashMap<u16, f64> = build_scores(&full, mode, players, all_maps, config)
+                .into_iter()
+                .map(|(score, map)| (map.id, score))
+                .collect();
+            for (score, map) in build_scores(&kept, mode, players, all_maps, config) {
+                let change = (score - before[&map.id]).abs() * 100.0;
+                if change > worst.0 {
+                    worst = (change, Some(map));
+                }
+            }
+        }
+    }
+    worst
+}
+
+/// Move all but the last `keep` plays of the log to the archive, if scoring only those plays
+/// (alongside the foreign logs) changes no map's chance by more than `tolerance` percentage
+/// points. Returns None if the log has no more than `keep` plays.
+pub fn compact(
+    keep: usize,
+    tolerance: f64,
+    maps: &Maps,
+    groups: &Groups,
+    config: &ScoringConfig,
+    foreign: &[Vec<LogEntry>],
+    by_name: bool,
+) -> Result<Option<Compaction>, Box<dyn Error>> {
+    // hold off appends until both files are written
+    let _lock = lock_for(LOG_FILE, true)?;
+    let lines = read_lines(LOG_FILE)?;
+
+    // split before the last `keep` lines that read as plays, bad lines go along with their neighbours
+    let plays: Vec<usize> = (0..lines.len())
+        .filter(|&i| LogLine::parse(lines[i].trim()).is_ok())
+        .collect();
+    if plays.len() <= keep {
+        return Ok(None);
+    }
+    let split = if keep == 0 {
+        lines.len()
+    } else {
+        plays[plays.len() - keep]
+    };
+
+    let read = |lines: &[String]| {
+        read_log_lines(
+            lines.iter().cloned().map(Ok),
+            maps,
+            groups,
+            Origin::Local,
+            by_name,
+        )
+        .map(|(entries, _)| entries)
+    };
+    let with_foreign = |log: Vec<LogEntry>| {
+        merge_logs(
+            std::iter::once(log)
+                .chain(foreign.iter().cloned())
+                .collect(),
+        )
+    };
+    let all_maps: Vec<RcMap> = maps.values().cloned().collect();
+    let (change, map) = score_change(
+        with_foreign(read(&lines)?),
+        with_foreign(read(&lines[split..])?),
+        &all_maps,
+        config,
+    );
+    if change > tolerance {
+        let map = map
+            .map(|m| format!("{} {} ({})", m.nickname, m.mode.name(), m.players))
+            .unwrap_or_default();
+        return Err(CompactError::ScoresChanged { map, change, keep }.into());
+    }
+
+    // archive first, a failure in between leaves plays in both files rather than neither
+    let mut archive = if Path::new(ARCHIVE_FILE).exists() {
+        fs::read_to_string(ARCHIVE_FILE)?
+    } else {
+        String::new()
+    };
+    if !archive.is_empty() && !archive.ends_with('\n') {
+        archive.push('\n');
+    }
+    let joined = |lines: &[String]| -> String { lines.iter().map(|l| l.clone() + "\n").collect() };
+    archive += &joined(&lines[..split]);
+    write_atomic(ARCHIVE_FILE, &archive)?;
+    write_atomic(LOG_FILE, &joined(&lines[split..]))?;
+
+    Ok(Some(Compaction {
+        archived: plays.len() - keep,
+        kept: keep,
+        change,
+    }))
+}
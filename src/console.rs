@@ -0,0 +1,11 @@
+use std::io::{self, stdin};
+
+/// A line typed at the console without surrounding whitespace, an error once input has ended
+/// so prompts stop instead of asking again forever
+pub fn read_line() -> io::Result<String> {
+    let mut input = String::new();
+    match stdin().read_line(&mut input)? {
+        0 => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "end of input")),
+        _ => Ok(input.trim().to_string()),
+    }
+}
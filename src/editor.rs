@@ -0,0 +1,141 @@
+use std::{
+    error::Error,
+    io::{stdout, Write},
+};
+
+use crate::catalog::{Catalog, EditError};
+use crate::console::read_line;
+use crate::map_data::{build_map_data, Mode};
+
+static HELP: &str = "\
+Commands:
+  list                               groups and their variants
+  show <gid>                         one group
+  add-group <name>                   new group under the next free gid
+  add-variant <gid> <mode> <players> new variant under the next free id
+  set <id> <field> <value>           field is nickname, players, mode, gag, disabled or weight
+  remove-variant <id>                retires the id, old plays of it are skipped
+  alias <id> <old id>                plays logged under the old id count for this one
+  write                              save the catalog
+  quit";
+
+fn arg<'a, T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = &'a str>,
+    name: &str,
+) -> Result<T, String> {
+    let a = args.next().ok_or_else(|| format!("missing {}", name))?;
+    a.parse().map_err(|_| format!("bad {}: '{}'", name, a))
+}
+
+/// Apply one command to a copy of the catalog, returning the copy if it changed anything
+fn apply(catalog: &Catalog, line: &str) -> Result<Option<Catalog>, Box<dyn Error>> {
+    let (cmd, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim();
+    let mut args = rest.split_whitespace();
+    let mut edited = catalog.clone();
+
+    match cmd {
+        "list" => {
+            for g in &catalog.groups {
+                let variants: Vec<String> = g
+                    .variants
+                    .iter()
+                    .map(|v| format!("{} {}", v.id, v.mode.name()))
+                    .collect();
+                println!("{:>4} {} [{}]", g.gid, g.name, variants.join(", "));
+            }
+            return Ok(None);
+        }
+        "show" => {
+            let gid = arg(&mut args, "gid")?;
+            let g = catalog.group(gid).ok_or(EditError::UnknownGroup(gid))?;
+            println!("{} {}", g.gid, g.name);
+            if !g.retired.is_empty() {
+                println!("  retired {:?}", g.retired);
+            }
+            for v in &g.variants {
+                println!("  {}", v);
+            }
+            return Ok(None);
+        }
+        "add-group" => {
+            let gid = edited.add_group(rest)?;
+            println!("Added group {} {}", gid, rest);
+        }
+        "add-variant" => {
+            let gid = arg(&mut args, "gid")?;
+            let mode: String = arg(&mut args, "mode")?;
+            let mode: Mode = mode.as_str().try_into()?;
+            let players = arg(&mut args, "players")?;
+            let id = edited.add_variant(gid, mode, players)?;
+            println!("Added map {} to group {}", id, gid);
+        }
+        "set" => {
+            let id = arg(&mut args, "id")?;
+            let field: String = arg(&mut args, "field")?;
+            // values like nicknames may have spaces
+            let value = rest.splitn(3, ' ').nth(2).unwrap_or_default().trim();
+            edited.set(id, &field, value)?;
+        }
+        "remove-variant" => {
+            let id = arg(&mut args, "id")?;
+            let v = edited.remove_variant(id)?;
+            println!("Removed map {}", v);
+        }
+        "alias" => {
+            let id = arg(&mut args, "id")?;
+            let old = arg(&mut args, "old id")?;
+            edited.add_alias(id, old)?;
+            println!("Plays of {} now count for {}", old, id);
+        }
+        _ => return Err(format!("unknown command '{}', try help", cmd).into()),
+    }
+
+    build_map_data(&edited).map_err(EditError::from)?;
+    Ok(Some(edited))
+}
+
+/// Edit the catalog at `path` from the console, every change is checked against the whole catalog
+pub fn run(path: &str) -> Result<(), Box<dyn Error>> {
+    let mut catalog = Catalog::load(path)?;
+    let mut unsaved = 0;
+
+    println!("Editing {} ({} groups)", path, catalog.groups.len());
+    println!("{}", HELP);
+    loop {
+        print!("catalog> ");
+        stdout().flush()?;
+        let line = read_line().unwrap_or_else(|_| "quit".to_string());
+
+        match line.as_str() {
+            "" => {}
+            "help" => println!("{}", HELP),
+            "write" => {
+                catalog.save(path)?;
+                println!("Wrote {}", path);
+                unsaved = 0;
+            }
+            "quit" => {
+                if unsaved > 0 {
+                    print!("Discard {} unsaved changes? (y/n) ", unsaved);
+                    stdout().flush()?;
+                    // at the end of input there's no one left to ask
+                    match read_line() {
+                        Ok(answer) if answer != "y" => continue,
+                        Ok(_) => {}
+                        Err(_) => println!("\nDiscarded {} unsaved changes", unsaved),
+                    }
+                }
+                return Ok(());
+            }
+            _ => match apply(&catalog, &line) {
+                Ok(Some(edited)) => {
+                    catalog = edited;
+                    unsaved += 1;
+                }
+                Ok(None) => {}
+                Err(e) => println!("{}", e),
+            },
+        }
+    }
+}
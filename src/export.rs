@@ -0,0 +1,141 @@
+use std::{error::Error, fs};
+
+use chrono::NaiveDate;
+
+use crate::files::write_atomic;
+use crate::play_log::LogEntry;
+use crate::session::label_sessions;
+use crate::stats::csv_field;
+
+static COLUMNS: [&str; 7] = ["date", "time", "mode", "map", "group", "players", "session"];
+
+/// Which plays to export, dates are inclusive and compared in UTC
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub session: Option<u32>,
+}
+
+impl ExportFilter {
+    fn allows(&self, entry: &LogEntry, session: u32) -> bool {
+        let date = entry.time.map(|t| t.date_naive());
+        // plays without a timestamp can't be placed within a date range
+        let in_range = match (self.from, self.to) {
+            (None, None) => true,
+            (from, to) => date
+                .is_some_and(|d| from.is_none_or(|from| from <= d) && to.is_none_or(|to| d <= to)),
+        };
+        in_range && self.session.is_none_or(|s| s == session)
+    }
+}
+
+/// The plays of the log joined with the catalog, one row of `COLUMNS` each
+fn rows(log: &[LogEntry], filter: &ExportFilter) -> Vec<[String; 7]> {
+    log.iter()
+        .zip(label_sessions(log))
+        .filter(|(entry, session)| filter.allows(entry, *session))
+        .map(|(entry, session)| {
+            let map = &entry.map;
+            [
+                entry
+                    .time
+                    .map(|t| t.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+                entry
+                    .time
+                    .map(|t| t.format("%H:%M").to_string())
+                    .unwrap_or_default(),
+                map.mode.name().to_string(),
+                map.nickname.clone(),
+                map.group().basename.clone(),
+                map.players.to_string(),
+                session.to_string(),
+            ]
+        })
+        .collect()
+}
+
+fn to_csv(rows: &[[String; 7]]) -> String {
+    let mut out = COLUMNS.join(",") + "\n";
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        out += &fields.join(",");
+        out += "\n";
+    }
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn to_html(rows: &[[String; 7]]) -> String {
+    let cells = |tag: &str, row: &[String]| -> String {
+        row.iter()
+            .map(|f| format!("<{0}>{1}</{0}>", tag, html_escape(f)))
+            .collect()
+    };
+    let header: Vec<String> = COLUMNS.iter().map(|c| c.to_string()).collect();
+    let body: String = rows
+        .iter()
+        .map(|row| format!("    <tr>{}</tr>\n", cells("td", row)))
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Maps Played</title>
+<style>
+  body {{ font-family: sans-serif; }}
+  table {{ border-collapse: collapse; }}
+  th, td {{ padding: 0.2em 0.8em; text-align: left; }}
+  tr:nth-child(even) {{ background: #f0f0f0; }}
+</style>
+</head>
+<body>
+<h1>Maps Played</h1>
+<p>{} plays, times in UTC</p>
+<table>
+  <thead>
+    <tr>{}</tr>
+  </thead>
+  <tbody>
+{}  </tbody>
+</table>
+</body>
+</html>
+",
+        rows.len(),
+        cells("th", &header),
+        body
+    )
+}
+
+/// Write the plays passing `filter` to `path`, as CSV or as an HTML page by its extension.
+/// Returns the number of plays written.
+pub fn export(
+    path: &str,
+    log: &[LogEntry],
+    filter: &ExportFilter,
+) -> Result<usize, Box<dyn Error>> {
+    let rows = rows(log, filter);
+    let contents = match path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()) {
+        Some(ext) if ext == "csv" => to_csv(&rows),
+        Some(ext) if ext == "html" || ext == "htm" => to_html(&rows),
+        _ => return Err(format!("{}: exports must end in .csv or .html", path).into()),
+    };
+
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        if !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir)?;
+        }
+    }
+    write_atomic(path, &contents)?;
+    Ok(rows.len())
+}
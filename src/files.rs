@@ -0,0 +1,70 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+/// Write a whole file by writing a temporary file next to it and renaming it over the original,
+/// so a crash or another reader never sees it half written
+pub fn write_atomic(path: &str, contents: &str) -> io::Result<()> {
+    let tmp = format!("{}.{}.tmp", path, std::process::id());
+    let mut f = File::create(&tmp)?;
+    f.write_all(contents.as_bytes())?;
+    f.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Lock `<path>.lock` in place of `path`, shared for reading or exclusive for writing, until the
+/// returned file is dropped. A file replaced by `write_atomic` can't carry its own lock, a writer
+/// waiting on the old file would go on to write into it after it was replaced.
+pub fn lock_for(path: &str, exclusive: bool) -> io::Result<File> {
+    let f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(format!("{}.lock", path))?;
+    if exclusive {
+        f.lock()?;
+    } else {
+        f.lock_shared()?;
+    }
+    Ok(f)
+}
+
+/// Share the lock on `<path>.lock` if the file's owner keeps one, for files this program only reads.
+/// The lock file is never created, the directory may not be ours to write to.
+pub fn lock_if_present(path: &str) -> Option<File> {
+    let f = File::open(format!("{}.lock", path)).ok()?;
+    f.lock_shared().ok()?;
+    Some(f)
+}
+
+/// Size and modification time of a file, to notice when another program changes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    /// A missing file stamps as empty
+    pub fn of(path: &str) -> io::Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(FileStamp {
+                len: 0,
+                modified: None,
+            });
+        }
+        Self::of_file(&File::open(path)?)
+    }
+
+    pub fn of_file(f: &File) -> io::Result<Self> {
+        let meta = f.metadata()?;
+        Ok(FileStamp {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+        })
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    error::Error,
+    fs,
+    io::{stdout, Write},
+};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+use crate::console::read_line;
+use crate::map_data::{Mode, RcMap};
+use crate::play_log::{log_line, logged_plays, merge_into_log};
+
+static AUTO_MATCH: f64 = 0.8; // similarity a fuzzy match needs to be taken without asking
+static AUTO_MARGIN: f64 = 0.15; // and how far ahead of the next best map it must be
+static CANDIDATES_SHOWN: usize = 5;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("{0}: needs a header with date, map and mode columns")]
+    MissingColumns(String),
+    #[error("{0} line {1}: {2}")]
+    BadRow(String, usize, String),
+}
+
+/// Split one CSV line into fields, quoted fields may hold commas and doubled quotes
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Dates with an optional time of day, taken as UTC
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let time = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })?;
+    Some(Utc.from_utc_datetime(&time))
+}
+
+struct Row {
+    line_num: usize,
+    time: DateTime<Utc>,
+    name: String,
+    mode: Mode,
+}
+
+fn read_rows(path: &str) -> Result<Vec<Row>, Box<dyn Error>> {
+    let raw = fs::read_to_string(path)?;
+    let mut lines = raw
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+
+    let header: Vec<String> = lines
+        .next()
+        .map(|(_, l)| csv_fields(&l.to_lowercase()))
+        .unwrap_or_default();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let (date_col, map_col, mode_col) = match (column("date"), column("map"), column("mode")) {
+        (Some(d), Some(m), Some(o)) => (d, m, o),
+        _ => return Err(ImportError::MissingColumns(path.to_string()).into()),
+    };
+    let time_col = column("time");
+
+    let mut rows = Vec::new();
+    for (idx, line) in lines {
+        let line_num = idx + 1;
+        let bad = |err: String| ImportError::BadRow(path.to_string(), line_num, err);
+        let fields = csv_fields(line);
+        let field = |col: usize| fields.get(col).map(String::as_str).unwrap_or_default();
+
+        let date = match time_col {
+            Some(t) if !field(t).is_empty() => format!("{} {}", field(date_col), field(t)),
+            _ => field(date_col).to_string(),
+        };
+        let time = parse_date(&date).ok_or_else(|| bad(format!("bad date '{}'", date)))?;
+        let mode: Mode = field(mode_col)
+            .try_into()
+            .map_err(|_| bad(format!("unknown mode '{}'", field(mode_col))))?;
+        let name = field(map_col).to_string();
+        if name.is_empty() {
+            Err(bad("missing map name".to_string()))?;
+        }
+
+        rows.push(Row {
+            line_num,
+            time,
+            name,
+            mode,
+        });
+    }
+
+    Ok(rows)
+}
+
+/// Lowercase letters and digits only, so "Neden 1" and "Neden-1" compare equal
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// How alike a name is to a map's nickname or group name, from 0 to 1
+fn similarity(name: &str, map: &RcMap) -> f64 {
+    let name = normalize(name);
+    let nickname = strsim::jaro_winkler(&name, &normalize(&map.nickname));
+    let basename = strsim::jaro_winkler(&name, &normalize(&map.group().basename));
+    nickname.max(basename)
+}
+
+enum Match {
+    Exact(RcMap),
+    Fuzzy(RcMap),
+    Chosen(RcMap),
+    Skipped,
+}
+
+impl Match {
+    fn map(&self) -> Option<&RcMap> {
+        match self {
+            Match::Exact(m) | Match::Fuzzy(m) | Match::Chosen(m) => Some(m),
+            Match::Skipped => None,
+        }
+    }
+
+    fn kind(&self) -> &str {
+        match self {
+            Match::Exact(_) => "exact",
+            Match::Fuzzy(_) => "fuzzy",
+            Match::Chosen(_) => "chosen",
+            Match::Skipped => "skipped",
+        }
+    }
+}
+
+fn ask(name: &str, mode: Mode, candidates: &[(f64, RcMap)]) -> Result<Match, Box<dyn Error>> {
+    println!("Which map is '{}' ({})?", name, mode.name());
+    for ((score, map), idx) in candidates.iter().zip(1..) {
+        println!(" ({}) {} ({:.0}% alike)", idx, map.map_info(), score * 100.);
+    }
+    println!(" (s) Skip these rows");
+    loop {
+        print!("> ");
+        stdout().flush()?;
+        let response = read_line()?;
+        if response == "s" || response.is_empty() {
+            return Ok(Match::Skipped);
+        }
+        match response.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= candidates.len() => {
+                return Ok(Match::Chosen(candidates[n - 1].1.clone()))
+            }
+            _ => println!("pick 1 to {} or s", candidates.len()),
+        }
+    }
+}
+
+/// Match a name to a map of the given mode, by name first, then by similarity, asking when unsure
+fn resolve(name: &str, mode: Mode, all_maps: &[RcMap]) -> Result<Match, Box<dyn Error>> {
+    let of_mode: Vec<&RcMap> = all_maps.iter().filter(|m| m.mode == mode).collect();
+
+    let normalized = normalize(name);
+    let exact: Vec<&&RcMap> = of_mode
+        .iter()
+        .filter(|m| normalize(&m.nickname) == normalized)
+        .collect();
+    let exact = if exact.is_empty() {
+        of_mode
+            .iter()
+            .filter(|m| normalize(&m.group().basename) == normalized)
+            .collect()
+    } else {
+        exact
+    };
+    if exact.len() == 1 {
+        return Ok(Match::Exact((*exact[0]).clone()));
+    }
+
+    let mut candidates: Vec<(f64, RcMap)> = of_mode
+        .iter()
+        .map(|m| (similarity(name, m), (*m).clone()))
+        .collect();
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.id.cmp(&b.1.id)));
+    candidates.truncate(CANDIDATES_SHOWN);
+
+    match candidates.as_slice() {
+        [] => Ok(Match::Skipped),
+        [(best, map)] if *best >= AUTO_MATCH => Ok(Match::Fuzzy(map.clone())),
+        [(best, map), (next, _), ..] if *best >= AUTO_MATCH && best - next >= AUTO_MARGIN => {
+            Ok(Match::Fuzzy(map.clone()))
+        }
+        _ => ask(name, mode, &candidates),
+    }
+}
+
+/// Import plays from a CSV file with date, map and mode columns (and optionally time) into the
+/// log, after showing what each row was matched to
+pub fn import(path: &str, all_maps: &[RcMap]) -> Result<(), Box<dyn Error>> {
+    let rows = read_rows(path)?;
+    println!("Read {} rows from {}", rows.len(), path);
+
+    // each distinct name is only matched, and asked about, once
+    let mut matches: HashMap<(String, Mode), Match> = HashMap::new();
+    for row in &rows {
+        let key = (row.name.to_lowercase(), row.mode);
+        if let Entry::Vacant(e) = matches.entry(key) {
+            e.insert(resolve(&row.name, row.mode, all_maps)?);
+        }
+    }
+
+    // so importing the same file twice doesn't count its plays twice
+    let logged = logged_plays()?;
+
+    println!();
+    println!(
+        "{:<6} {:<17} {:<30} {:<8} Map",
+        "Line", "Date", "Name", "Match"
+    );
+    let mut lines = Vec::new();
+    for row in &rows {
+        let found = &matches[&(row.name.to_lowercase(), row.mode)];
+        let map = found.map();
+        let already = map.is_some_and(|m| logged.contains(&(row.time, m.id)));
+        println!(
+            "{:<6} {:<17} {:<30} {:<8} {}",
+            row.line_num,
+            row.time.format("%Y-%m-%d %H:%M"),
+            format!("{} {}", row.name, row.mode.name()),
+            if already { "logged" } else { found.kind() },
+            map.map(|m| m.map_info()).unwrap_or_default()
+        );
+        if let Some(map) = map.filter(|_| !already) {
+            let notes = [("imported", path.to_string())];
+            lines.push((row.time, log_line(map, row.time, &notes)));
+        }
+    }
+
+    println!();
+    if lines.is_empty() {
+        println!("Nothing to import");
+        return Ok(());
+    }
+    print!(
+        "Import {} plays, skipping {}? (y/n) ",
+        lines.len(),
+        rows.len() - lines.len()
+    );
+    stdout().flush()?;
+    if read_line()? != "y" {
+        println!("Nothing imported");
+        return Ok(());
+    }
+
+    let count = lines.len();
+    let backup = merge_into_log(lines)?;
+    println!("Imported {} plays, the old log is in {}", count, backup);
+    Ok(())
+}
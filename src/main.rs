@@ -1,14 +1,31 @@
 use std::{
-    cmp::Ordering,
     collections::HashMap,
     error::Error,
     fmt::{Debug, Display},
     io::Write,
+    path::Path,
     rc::Rc,
 };
 
 mod coloring;
 
+mod console;
+use console::read_line;
+
+mod catalog;
+use catalog::Catalog;
+
+mod editor;
+
+mod files;
+use files::FileStamp;
+
+mod import;
+
+mod export;
+
+mod compact;
+
 mod map_data;
 use ansi_term::{ANSIString, Color, Style};
 use coloring::MaybeColor;
@@ -16,17 +33,42 @@ use map_data::*;
 
 mod play_log;
 use play_log::*;
-use rand::random;
 
 mod map_scoring;
 use map_scoring::*;
 
+mod options;
+use options::{Options, OutputFormat};
+
+mod voting;
+use voting::*;
+
+mod sampling;
+use sampling::{pick_random_maps, sort_score, CHOICE_CT};
+
+mod session;
+use session::Session;
+
+mod stats;
+use stats::Stats;
+
+mod simulation;
+
+mod tune;
+
 enum ModeAction {
     SelectMap(usize),
     ChangeMode,
     SetPlayerCt,
     Percents,
     AllMaps,
+    Favorite,
+    Ban,
+    Vote,
+    Roster,
+    Tags,
+    Pool,
+    NewSession,
     Shuffle,
     Quit,
 }
@@ -41,15 +83,6 @@ macro_rules! print_flush {
     };
 }
 
-fn read_line() -> String {
-    let mut input = String::new();
-    std::io::stdin()
-        .read_line(&mut input)
-        .expect("Error: unable to read user input");
-
-    input
-}
-
 fn choice<'a, S>(choice: S) -> ANSIString<'a>
 where
     S: ToString,
@@ -64,26 +97,44 @@ where
 fn print_map_choices(
     mode: Mode,
     players: u16,
+    config: &ScoringConfig,
     random_maps: &[(f64, RcMap)],
+    offered: Option<&HashMap<u16, f64>>,
 ) -> Result<(), Box<dyn Error>> {
     let spaces: usize = usize::from(random_maps.len() > 9) + 1;
 
     let print_map_choice = |idx: usize, random_maps: &[(f64, RcMap)]| {
         let (percent, map) = &random_maps[idx];
+        // the chance this map would be among the choices at all
+        let offered = offered
+            .and_then(|o| o.get(&map.id))
+            .map(|o| format!(" (offered {:.1}%)", o * 100.))
+            .unwrap_or_default();
         println!(
-            " ({}) {} ({}) {}",
+            " ({}) {} ({}) {} {}",
             choice(format!("{: >1$}", idx + 1, spaces)),
             map.nickname,
             map.players,
             Style::new()
                 .italic()
                 .maybe_color()
-                .paint(format!("{:.2}%", percent * 100.))
+                .paint(format!("{:.2}%{}", percent * 100., offered)),
+            Style::new()
+                .dimmed()
+                .maybe_color()
+                .paint(map.all_tags().join(", "))
         );
     };
 
     println!();
-    println!("Mode {} for {} players", mode, players);
+    let mut header = format!("Mode {} for {} players", mode, players);
+    if let Some(pool) = &config.pool {
+        header += &format!(", pool {}", pool.name);
+    }
+    if !config.tags.is_empty() {
+        header += &format!(", tags {}", config.tags);
+    }
+    println!("{}", header);
     for i in 0..random_maps.len() {
         print_map_choice(i, random_maps);
     }
@@ -92,6 +143,13 @@ fn print_map_choices(
     println!(" ({}{}) Set Players", space, choice('p'));
     println!(" ({}{}) Show Map Percents", space, choice('%'));
     println!(" ({}{}) Choose From All Maps", space, choice('a'));
+    println!(" ({}{}) Favorite a Map", space, choice('f'));
+    println!(" ({}{}) Ban a Map", space, choice('b'));
+    println!(" ({}{}) Vote", space, choice('v'));
+    println!(" ({}{}) Set Roster", space, choice('r'));
+    println!(" ({}{}) Filter by Tags", space, choice('t'));
+    println!(" ({}{}) Switch Pool", space, choice('o'));
+    println!(" ({}{}) End Session & Start New", space, choice('n'));
     println!(" ({}{}) Shuffle", space, choice('s'));
     println!(" ({}{}) Quit", space, choice('q'));
     print_flush!("> ");
@@ -105,7 +163,7 @@ where
     E: Display + Debug,
 {
     loop {
-        let response = read_line().trim().to_string();
+        let response = read_line()?;
         let response = f(response);
         match response {
             Ok(v) => break Ok(v),
@@ -138,6 +196,13 @@ fn get_mode_action(map_ct: usize) -> Result<ModeAction, Box<dyn Error>> {
                 "p" => Ok(ModeAction::SetPlayerCt),
                 "%" => Ok(ModeAction::Percents),
                 "a" => Ok(ModeAction::AllMaps),
+                "f" => Ok(ModeAction::Favorite),
+                "b" => Ok(ModeAction::Ban),
+                "v" => Ok(ModeAction::Vote),
+                "r" => Ok(ModeAction::Roster),
+                "t" => Ok(ModeAction::Tags),
+                "o" => Ok(ModeAction::Pool),
+                "n" => Ok(ModeAction::NewSession),
                 "s" => Ok(ModeAction::Shuffle),
                 "q" => Ok(ModeAction::Quit),
                 _ => Err("bad response".into()),
@@ -157,6 +222,43 @@ fn prompt_for_player_ct() -> Result<u16, Box<dyn Error>> {
     })
 }
 
+fn prompt_for_roster() -> Result<Vec<String>, Box<dyn Error>> {
+    print_flush!("Who is playing? (names separated by commas, empty to clear)\n> ");
+    read_until_valid(|response| {
+        if response.contains('|') {
+            Err("names can't contain '|'")
+        } else {
+            Ok(parse_roster(&response))
+        }
+    })
+}
+
+fn prompt_for_tags() -> Result<TagFilter, Box<dyn Error>> {
+    print_flush!("Which tags? (e.g. \"small -water\", empty to clear)\n> ");
+    Ok(TagFilter::parse(&read_line()?))
+}
+
+/// None to cancel, Some(None) for every map
+fn prompt_for_pool(pools: &[Pool]) -> Result<Option<Option<Pool>>, Box<dyn Error>> {
+    println!("Select Pool:");
+    println!(" ({}) All Maps", choice(0));
+    for (pool, idx) in pools.iter().zip(1..) {
+        println!(" ({}) {}", choice(idx), pool.name);
+    }
+    println!(" ({}) Cancel", choice('c'));
+    print_flush!("> ");
+    read_until_valid(|response| {
+        if response == "c" {
+            return Ok(None);
+        }
+        match response.parse::<usize>() {
+            Ok(0) => Ok(Some(None)),
+            Ok(n) if n <= pools.len() => Ok(Some(Some(pools[n - 1].clone()))),
+            _ => Err(format!("pool must be between 0 and {}", pools.len())),
+        }
+    })
+}
+
 fn prompt_for_mode() -> Result<Option<Mode>, Box<dyn Error>> {
     println!("Select Mode:");
     for (mode, idx) in Mode::ordered().iter().zip(1..) {
@@ -176,73 +278,98 @@ fn prompt_for_mode() -> Result<Option<Mode>, Box<dyn Error>> {
     })
 }
 
-fn sort_score<T>(a: &(f64, T), b: &(f64, T)) -> Ordering {
-    a.0.partial_cmp(&b.0).unwrap().reverse()
+fn prompt_for_map(random_maps: &[(f64, RcMap)]) -> Result<Option<RcMap>, Box<dyn Error>> {
+    print_flush!("Which map? ({} to cancel)\n> ", choice('c'));
+    read_until_valid(|response| {
+        if response == "c" {
+            return Ok(None);
+        }
+        match response.parse::<usize>() {
+            Ok(n) if n <= random_maps.len() && n > 0 => Ok(Some(random_maps[n - 1].1.clone())),
+            _ => Err(format!("map must be between 1 and {}", random_maps.len())),
+        }
+    })
 }
 
-fn pick_random_maps(
-    log: &[RcMap],
-    mode: Mode,
-    players: u16,
-    all_maps: &[RcMap],
-    quiet: bool,
-) -> Result<Vec<(f64, RcMap)>, Box<dyn Error>> {
-    if !quiet {
-        print_flush!("Selecting Options");
-    }
-
-    let mut scores = build_scores(log, mode, players, all_maps);
-    assert!(!scores.is_empty());
-
-    if !quiet {
-        print_flush!(".");
+/// Put the offered maps to a vote, returning the winner and the totals to log with it
+fn run_vote(
+    method: VoteMethod,
+    random_maps: &[(f64, RcMap)],
+) -> Result<Option<(RcMap, String)>, Box<dyn Error>> {
+    if random_maps.is_empty() {
+        return Ok(None);
     }
 
-    let mut random_maps: Vec<(f64, RcMap)> = Vec::new();
+    println!(
+        "Enter each player's ballot, choices in order of preference, {} to veto (e.g. {})",
+        choice('!'),
+        choice("2 1 !3")
+    );
+    println!("Enter an empty line when done");
 
+    let mut ballots = Vec::new();
     loop {
-        let sum: f64 = scores.iter().map(|s| s.0).sum();
-        let mut random: f64 = random::<f64>() * sum;
-        for ((s, m), idx) in scores.iter().zip(0..) {
-            random -= *s;
-            if random <= 0. {
-                assert!(!random_maps.iter().any(|(_, e)| m.id == e.id));
-                random_maps.push((*s, m.clone()));
-                scores.remove(idx);
-                if !quiet {
-                    print_flush!(".");
-                }
-                break;
+        print_flush!("Ballot {}> ", ballots.len() + 1);
+        let ballot = read_until_valid(|response| {
+            if response.is_empty() {
+                Ok(None)
+            } else {
+                Ballot::parse(&response, random_maps.len()).map(Some)
             }
+        })?;
+        match ballot {
+            Some(b) => ballots.push(b),
+            None => break,
         }
+    }
 
-        if random_maps.len() >= 3 {
-            break;
-        }
+    if ballots.is_empty() {
+        println!("No ballots cast");
+        return Ok(None);
     }
 
-    random_maps.sort_unstable_by(sort_score);
+    let scores: Vec<f64> = random_maps.iter().map(|(s, _)| *s).collect();
+    let tally = tally(method, &scores, &ballots);
 
-    Ok(random_maps)
+    println!();
+    println!("Results ({}, {} ballots)", method, ballots.len());
+    let mut totals = Vec::new();
+    for (idx, (_, map)) in random_maps.iter().enumerate() {
+        let total = if tally.vetoed[idx] {
+            "vetoed".to_string()
+        } else {
+            tally.totals[idx].to_string()
+        };
+        println!("  {} {}", map.map_info(), choice(&total));
+        totals.push(format!("{} {}", map.nickname, total));
+    }
+
+    let summary = format!("({}) {}", method, totals.join(", "));
+    Ok(Some((random_maps[tally.winner].1.clone(), summary)))
 }
 
-fn print_all_maps_for_mode(log: &[RcMap], all_maps: &[RcMap]) -> Result<(), Box<dyn Error>> {
+fn print_all_maps_for_mode(
+    state: &ScoringState,
+    all_maps: &[RcMap],
+    config: &ScoringConfig,
+) -> Result<(), Box<dyn Error>> {
     if let Some(mode) = prompt_for_mode()? {
-        let mut scores = build_scores(log, mode, 0, all_maps);
-        assert!(!scores.is_empty());
+        let mut scores = build_scores(state, mode, 0, all_maps, config);
         scores.sort_unstable_by(sort_score);
 
         println!();
         println!("All maps for {}", mode);
         for (score, map) in scores {
+            let tags = map.all_tags().join(", ");
             println!(
-                "  {} ({}) {}",
+                "  {} ({}) {} {}",
                 map.nickname,
                 map.players,
                 Style::new()
                     .italic()
                     .maybe_color()
-                    .paint(format!("{:.2}%", score * 100.))
+                    .paint(format!("{:.2}%", score * 100.)),
+                Style::new().dimmed().maybe_color().paint(tags)
             );
         }
         println!();
@@ -251,46 +378,272 @@ fn print_all_maps_for_mode(log: &[RcMap], all_maps: &[RcMap]) -> Result<(), Box<
     Ok(())
 }
 
+/// The local log followed by any foreign logs
+fn load_logs(
+    maps: &Maps,
+    groups: &Groups,
+    options: &Options,
+    quiet: bool,
+) -> Result<Vec<Vec<LogEntry>>, Box<dyn Error>> {
+    // warnings about the logs were given when they were first loaded
+    let log = load_log(maps, groups, options.resolve_by_name, quiet)?;
+    if !quiet {
+        println!("Loaded Log with {} entries", log.len());
+    }
+
+    let mut logs = vec![log];
+    for path in &options.foreign_logs {
+        let foreign = load_log_file(
+            path,
+            maps,
+            groups,
+            Origin::Foreign(options.foreign_weight),
+            options.resolve_by_name,
+            quiet,
+        )?;
+        if !quiet {
+            println!("Loaded Foreign Log {} with {} entries", path, foreign.len());
+        }
+        logs.push(foreign);
+    }
+
+    Ok(logs)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let (groups, maps) = load_map_data()?;
+    let options = Options::parse(std::env::args().skip(1))?;
+
+    if options.catalog_schema {
+        println!("{}", Catalog::schema());
+        return Ok(());
+    }
+
+    let catalog_path = options
+        .catalog
+        .clone()
+        .unwrap_or_else(Catalog::default_path);
+
+    if options.edit_catalog {
+        return editor::run(&catalog_path);
+    }
+
+    if let Some(out) = &options.convert {
+        let catalog = Catalog::load(&catalog_path)?;
+        build_map_data(&catalog)?;
+        catalog.save(out)?;
+        println!(
+            "Wrote {} groups from {} to {}",
+            catalog.groups.len(),
+            catalog_path,
+            out
+        );
+        return Ok(());
+    }
+
+    let (groups, maps) = load_map_data(&catalog_path)?;
 
     let all_maps: Vec<RcMap> = maps.values().map(Rc::clone).collect();
 
-    let args: Vec<String> = std::env::args().collect();
-    if args.get(1).filter(|a| *a == "--simulate").is_some() {
+    let mut config = ScoringConfig::default();
+    if let Some(path) = &options.profile {
+        let profile = load_profile(path, &maps)?;
+        config.weights.profile = profile.weights;
+        config.params = profile.scoring;
+    }
+    if let Some(path) = &options.roster {
+        config.roster = parse_roster(&std::fs::read_to_string(path)?.replace('\n', ","));
+    }
+    config.per_player = options.per_player;
+    config.tags = options.tags.clone();
+
+    let pools = match &options.pools {
+        Some(path) => load_pools(path, &maps)?,
+        None if Path::new(POOLS_FILE).exists() => load_pools(POOLS_FILE, &maps)?,
+        None => Vec::new(),
+    };
+    config.pool = match &options.pool {
+        Some(name) => Some(
+            pools
+                .iter()
+                .find(|p| p.name.eq_ignore_ascii_case(name))
+                .cloned()
+                .ok_or_else(|| format!("Unknown pool {}", name))?,
+        ),
+        None => Pool::active(&pools, chrono::Local::now().date_naive()).cloned(),
+    };
+
+    if options.simulate {
+        let groups: Vec<RcGroup> = groups.values().map(Rc::clone).collect();
+        let simulation = &options.simulation;
+        if options.trials > 1 {
+            let threads = options
+                .threads
+                .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+                .unwrap_or(1);
+            let results = simulation.run_trials(options.trials, threads, &config, || {
+                load_map_data(&catalog_path)
+            })?;
+            simulation::print_aggregate(&results, &groups);
+        } else {
+            let log = simulation.run(&all_maps, &config)?;
+            let (maps, modes) = simulation.report(&log, &groups, &all_maps, &config);
+            simulation::print_report(&maps, &modes);
+        }
+        return Ok(());
+    }
+
+    if options.tune {
+        let groups: Vec<RcGroup> = groups.values().map(Rc::clone).collect();
+        let trials = tune::tune(
+            &options.simulation,
+            &groups,
+            &all_maps,
+            &mut config,
+            options.tune_samples,
+        )?;
+        tune::print_trials(&trials);
+        return Ok(());
+    }
+
+    if let Some(path) = &options.import {
+        return import::import(path, &all_maps);
+    }
+
+    if let Some(path) = &options.export {
+        let log = load_history(&maps, &groups, options.resolve_by_name)?;
+        let count = export::export(path, &log, &options.export_filter)?;
+        println!("Wrote {} plays to {}", count, path);
+        return Ok(());
+    }
+
+    if options.check_log {
+        let errors = check_log(&maps, &groups, options.resolve_by_name)?;
+        for e in &errors {
+            println!("{}", e);
+        }
+        println!("{} lines to look at", errors.len());
+        return Ok(());
+    }
+
+    if options.repair {
+        match repair_log(&maps, &groups, options.resolve_by_name)? {
+            Some((dropped, backup)) => {
+                println!(
+                    "Dropped {} bad lines, the old log is in {}",
+                    dropped, backup
+                )
+            }
+            None => println!("Nothing to repair"),
+        }
+        return Ok(());
+    }
+
+    if let Some(keep) = options.compact {
+        let foreign = load_logs(&maps, &groups, &options, false)?.split_off(1);
+        let compacted = compact::compact(
+            keep,
+            options.tolerance,
+            &maps,
+            &groups,
+            &config,
+            &foreign,
+            options.resolve_by_name,
+        )?;
+        match compacted {
+            Some(c) => println!(
+                "Archived {} plays to {}, kept {}, scores changed by at most {:.3} points",
+                c.archived, ARCHIVE_FILE, c.kept, c.change
+            ),
+            None => println!("Nothing to compact"),
+        }
+        return Ok(());
+    }
+
+    if options.stats {
+        let log = load_history(&maps, &groups, options.resolve_by_name)?;
         let groups: Vec<RcGroup> = groups.values().map(Rc::clone).collect();
-        simulate(&groups, &all_maps)?;
+        let stats = Stats::compute(&log, &groups);
+        match options.format {
+            OutputFormat::Table => stats.print_table(),
+            OutputFormat::Csv => print!("{}", stats.to_csv()),
+            OutputFormat::Json => println!("{}", stats.to_json().pretty(4)),
+        }
         return Ok(());
     }
 
     println!("Loaded {} maps", maps.len());
+    if let Some(pool) = &config.pool {
+        println!("Offering maps from pool {}", pool.name);
+    }
+
+    let log_paths: Vec<&str> = std::iter::once(LOG_FILE)
+        .chain(options.foreign_logs.iter().map(String::as_str))
+        .collect();
+    let stamp_logs = || -> std::io::Result<Vec<FileStamp>> {
+        log_paths.iter().map(|p| FileStamp::of(p)).collect()
+    };
 
-    let mut log = load_log(&maps)?;
-    println!("Loaded Log with {} entries", log.len());
+    let mut stamps = stamp_logs()?;
+    let logs = load_logs(&maps, &groups, &options, false)?;
+    let log = &logs[0];
 
     // Initial state
     let mut mode = match log.last() {
         None => Mode::TD,
-        Some(m) => m.mode.next(),
+        Some(e) => e.map.mode.next(),
     };
+
+    // with the archive, so maps played only before the log was compacted aren't new
+    let mut history = load_archive(&maps, &groups, options.resolve_by_name)?;
+    history.extend(log.iter().cloned());
+    let mut session = Session::resume_or_start(&history, chrono::Utc::now());
+    println!("Session {}", session.id);
+
+    let mut state = ScoringState::from_log(merge_logs(logs), &all_maps, &config.params);
+    // set when our own append found someone else had written to the log
+    let mut stale = false;
+
     let mut players = 16u16;
 
     let mut show_all_maps = false;
     // main loop
     loop {
-        let random_maps = if show_all_maps {
+        // another console or a bot may have logged plays since
+        if stale || stamp_logs()? != stamps {
+            stale = false;
+            stamps = stamp_logs()?;
+            let logs = load_logs(&maps, &groups, &options, true)?;
+            state = ScoringState::from_log(merge_logs(logs), &all_maps, &config.params);
+            println!(
+                "\nThe log changed on disk, reloaded {} entries",
+                state.log().len()
+            );
+        }
+
+        let (random_maps, offered) = if show_all_maps {
             show_all_maps = false;
-            build_scores(&log, mode, 0, &all_maps)
+            (build_scores(&state, mode, 0, &all_maps, &config), None)
         } else {
-            pick_random_maps(&log, mode, players, &all_maps, false)?
+            let scores = build_scores(&state, mode, players, &all_maps, &config);
+            let offered = options.sampler.inclusion_probabilities(&scores, CHOICE_CT);
+            let recent = options.diversity.recent(state.log());
+            let random_maps =
+                pick_random_maps(scores, options.sampler, &options.diversity, &recent, false)?;
+            (random_maps, Some(offered))
         };
-        print_map_choices(mode, players, &random_maps)?;
+        if random_maps.is_empty() {
+            println!("\nNo maps available for {} with {} players", mode, players);
+        }
+        print_map_choices(mode, players, &config, &random_maps, offered.as_ref())?;
 
         match get_mode_action(random_maps.len())? {
             ModeAction::SelectMap(n) => {
                 let map = random_maps.get(n).unwrap().1.clone();
-                append_log(map.as_ref())?;
-                log.push(map.clone());
+                let (entry, changed) =
+                    append_log(&map, session.id, &config.roster, &[], &mut stamps[0])?;
+                stale |= changed;
+                state.push(entry);
+                session.played(&map);
                 mode = mode.next();
                 println!("{} Selected. Have Fun!\n", map.map_info());
             }
@@ -300,55 +653,59 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
             ModeAction::SetPlayerCt => players = prompt_for_player_ct()?,
-            ModeAction::Percents => print_all_maps_for_mode(&log, &all_maps)?,
+            ModeAction::Percents => print_all_maps_for_mode(&state, &all_maps, &config)?,
             ModeAction::AllMaps => show_all_maps = true,
-            ModeAction::Shuffle => {} // No action required, just loop
-            ModeAction::Quit => return Ok(()),
-        }
-    }
-}
-
-fn simulate(all_groups: &[RcGroup], all_maps: &[RcMap]) -> Result<(), Box<dyn Error>> {
-    let mut log = Vec::new();
-    let mut mode = Mode::TD;
-
-    for _ in 0..10_000 {
-        let random_maps = pick_random_maps(&log, mode, 16, all_maps, true)?;
-        let map = &random_maps.get(0).unwrap().1;
-
-        log.push(map.clone());
-        mode = mode.next();
-    }
-
-    let mut counts: HashMap<u16, u32> = HashMap::new();
-
-    for m in log {
-        let e = counts.entry(m.id);
-        match e {
-            std::collections::hash_map::Entry::Occupied(mut e) => {
-                let v = e.get_mut();
-                *v += 1;
+            ModeAction::Vote => {
+                if let Some((map, totals)) = run_vote(options.vote_method, &random_maps)? {
+                    let notes = [("votes", totals)];
+                    let (entry, changed) =
+                        append_log(&map, session.id, &config.roster, &notes, &mut stamps[0])?;
+                    stale |= changed;
+                    state.push(entry);
+                    session.played(&map);
+                    mode = mode.next();
+                    println!("{} Wins the Vote. Have Fun!\n", map.map_info());
+                }
             }
-            std::collections::hash_map::Entry::Vacant(e) => {
-                e.insert(1);
+            ModeAction::Roster => {
+                config.roster = prompt_for_roster()?;
+                println!("{} players on the roster", config.roster.len());
             }
-        }
-    }
-
-    for mode in Mode::ordered() {
-        for group in all_groups {
-            for map in &group.variants {
-                if map.mode != mode {
-                    continue;
+            ModeAction::Pool => {
+                if let Some(pool) = prompt_for_pool(&pools)? {
+                    config.pool = pool;
                 }
-
-                let ct = counts.get(&map.id);
-                if let Some(ct) = ct {
-                    println!("\"{}\",\"{}\",{}", mode, map.nickname, ct);
+            }
+            ModeAction::Tags => {
+                config.tags = prompt_for_tags()?;
+                if !config.tags.is_empty() {
+                    println!("Only offering maps matching {}", config.tags);
                 }
             }
+            ModeAction::Favorite => {
+                if let Some(map) = prompt_for_map(&random_maps)? {
+                    config.weights.favorite(&map);
+                    println!("{} Favorited for this session", map.map_info());
+                }
+            }
+            ModeAction::Ban => {
+                if let Some(map) = prompt_for_map(&random_maps)? {
+                    config.weights.ban(&map);
+                    println!("{} Banned for this session", map.map_info());
+                }
+            }
+            ModeAction::Shuffle => {} // No action required, just loop
+            ModeAction::NewSession => {
+                session.print_summary();
+                session = session.next();
+                println!("Session {} Started", session.id);
+            }
+            ModeAction::Quit => {
+                if !session.plays.is_empty() {
+                    session.print_summary();
+                }
+                return Ok(());
+            }
         }
     }
-
-    Ok(())
 }
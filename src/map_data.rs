@@ -1,11 +1,30 @@
-use std::{cell::RefCell, collections::HashMap, error::Error, fmt::Display, fs, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    fs,
+    rc::Rc,
+};
+
+use chrono::NaiveDate;
 
 use ansi_term::{Color, Style};
 use json::JsonValue;
-
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject, StringValidation},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::{Catalog, CatalogError};
 use crate::coloring::MaybeColor;
+use crate::map_scoring::{Pool, ScoringParams, TagFilter};
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+/// Read in any capitalization, like the console and the log, and written as [`Mode::name`]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String")]
 pub enum Mode {
     TD,      // lightish blue
     DM,      // red
@@ -113,6 +132,54 @@ impl TryInto<Mode> for &str {
     }
 }
 
+impl TryFrom<String> for Mode {
+    type Error = UnknownMode;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.as_str().try_into()
+    }
+}
+
+impl JsonSchema for Mode {
+    fn schema_name() -> String {
+        "Mode".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        // JSON Schema patterns have no case-insensitive flag, so spell out both cases of each letter
+        let names: Vec<String> = Mode::ordered()
+            .iter()
+            .map(|m| {
+                m.name()
+                    .chars()
+                    .map(|c| format!("[{}{}]", c.to_ascii_uppercase(), c.to_ascii_lowercase()))
+                    .collect()
+            })
+            .collect();
+
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(format!("^({})$", names.join("|"))),
+                ..Default::default()
+            })),
+            metadata: Some(Box::new(Metadata {
+                description: Some(format!(
+                    "One of {}, in any capitalization",
+                    Mode::ordered()
+                        .iter()
+                        .map(Mode::name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 impl Display for Mode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.console_color().maybe_color().paint(self.name()).fmt(f)
@@ -124,6 +191,18 @@ pub struct MapGroup {
     pub gid: u16,
     pub basename: String,
     pub variants: Vec<Rc<Map>>,
+    /// free-form descriptions like theme, visual style or size
+    pub tags: Vec<String>,
+    /// groups that look or play alike, playing one penalizes the others a little
+    similar: RefCell<Vec<u16>>,
+    /// ids of variants that were removed
+    pub retired: Vec<u16>,
+}
+
+impl MapGroup {
+    pub fn is_similar(&self, other: &MapGroup) -> bool {
+        self.similar.borrow().contains(&other.gid)
+    }
 }
 
 impl PartialEq for MapGroup {
@@ -142,6 +221,12 @@ pub struct Map {
     pub players: u16,
     pub is_gag: bool,
     pub disabled: bool,
+    /// multiplier on the map's score, 0 keeps it from being offered
+    pub weight: f64,
+    /// free-form descriptions of this variant, on top of its group's
+    pub tags: Vec<String>,
+    /// old ids this variant replaced
+    pub aliases: Vec<u16>,
 }
 
 impl PartialEq for Map {
@@ -154,8 +239,8 @@ impl Eq for Map {}
 
 impl std::fmt::Debug for Map {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Map{{id: {}, group: {}, nickname: {}, mode: {}, players: {}, is_gag: {}, disabled: {} }}", 
-        self.id, self.group().gid, self.nickname, self.mode, self.players, self.is_gag, self.disabled,
+        write!(f, "Map{{id: {}, group: {}, nickname: {}, mode: {}, players: {}, is_gag: {}, disabled: {}, weight: {} }}", 
+        self.id, self.group().gid, self.nickname, self.mode, self.players, self.is_gag, self.disabled, self.weight,
         )
     }
 }
@@ -167,28 +252,19 @@ impl Map {
         g.unwrap().clone()
     }
 
-    pub fn map_info(&self) -> String {
-        format!("{} {} ({})", self.nickname, self.mode, self.players)
+    /// The tags of the variant followed by those of its group
+    pub fn all_tags(&self) -> Vec<String> {
+        let mut tags = self.tags.clone();
+        tags.extend(self.group().tags.iter().cloned());
+        tags
     }
-}
-
-#[derive(thiserror::Error, Debug)]
-#[error("{1}: {0}")]
-pub struct GroupError(JsonValue, String);
 
-impl GroupError {
-    fn new(j: &JsonValue, err: &str) -> Self {
-        GroupError(j.clone(), err.to_string())
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.all_tags().iter().any(|t| t.eq_ignore_ascii_case(tag))
     }
-}
-
-#[derive(thiserror::Error, Debug)]
-#[error("(gid {0}) {2}: {1}")]
-pub struct MapError(u16, JsonValue, String);
 
-impl MapError {
-    fn new(gid: u16, j: &JsonValue, err: &str) -> Self {
-        MapError(gid, j.clone(), err.to_string())
+    pub fn map_info(&self) -> String {
+        format!("{} {} ({})", self.nickname, self.mode, self.players)
     }
 }
 
@@ -197,113 +273,71 @@ pub type Groups = HashMap<u16, RcGroup>;
 pub type RcMap = Rc<Map>;
 pub type Maps = HashMap<u16, RcMap>;
 
-pub fn load_map_data() -> Result<(Groups, Maps), Box<dyn Error>> {
-    let raw_json = fs::read_to_string("all_maps.json")?;
-    let json = json::parse(&raw_json)?;
+pub fn load_map_data(path: &str) -> Result<(Groups, Maps), Box<dyn Error>> {
+    Ok(build_map_data(&Catalog::load(path)?)?)
+}
 
+/// Link up the groups and maps described by a catalog
+pub fn build_map_data(catalog: &Catalog) -> Result<(Groups, Maps), CatalogError> {
     let mut groups: HashMap<u16, Rc<MapGroup>> = HashMap::new();
     let mut maps: HashMap<u16, Rc<Map>> = HashMap::new();
 
-    assert!(json.is_array(), "map file must be a list");
-
-    for g in json.members() {
-        let basename = &g["name"];
-        let gid = &g["gid"];
-        let variants = &g["variants"];
-
-        let gid = gid
-            .as_u16()
-            .ok_or_else(|| GroupError(gid.clone(), "gid not a u16".to_string()))?;
-        let basename = basename
-            .as_str()
-            .filter(|b| !b.is_empty())
-            .ok_or_else(|| GroupError::new(basename, "basename not a string not a u16"))?
-            .to_string();
-
-        assert!(
-            variants.is_array(),
-            "group {} needs a list of variants",
-            gid
-        );
+    for g in &catalog.groups {
+        let gid = g.gid;
+        if g.name.is_empty() {
+            Err(CatalogError::EmptyName(gid))?;
+        }
+        if g.tags.iter().any(String::is_empty) {
+            Err(CatalogError::EmptyTag(gid))?;
+        }
 
         let mut group = MapGroup {
-            basename: basename.clone(),
+            basename: g.name.clone(),
             gid,
             variants: Vec::new(),
+            tags: g.tags.clone(),
+            similar: RefCell::new(Vec::new()),
+            retired: g.retired.clone(),
         };
 
-        for v in variants.members() {
-            let id = &v["id"];
-            let players = &v["players"];
-            let mode = &v["mode"];
-            let is_gag = &v["gag"];
-            let nickname = &v["nickname"];
-            let disabled = &v["disabled"];
-
-            let id = id
-                .as_u16()
-                .ok_or_else(|| MapError::new(gid, id, "map id must be a u16"))?;
-            let players = players
-                .as_u16()
-                .ok_or_else(|| MapError::new(gid, players, "players id must be a u16"))?;
-            let mode_i = mode
-                .as_str()
-                .ok_or_else(|| MapError::new(gid, mode, "map mode must be a string"))?;
-            let mode: Mode = mode_i
-                .try_into()
-                .map_err(|_| MapError::new(gid, mode, "unknown map mode"))?;
-            let is_gag = if is_gag.is_null() {
-                false
-            } else {
-                is_gag
-                    .as_bool()
-                    .ok_or_else(|| MapError::new(gid, is_gag, "gag must be absent or a boolean"))?
-            };
-
-            let nickname = if nickname.is_null() {
-                basename.to_string()
-            } else {
-                nickname
-                    .as_str()
-                    .filter(|s| !s.is_empty())
-                    .ok_or_else(|| {
-                        MapError::new(gid, nickname, "nickname must be absent or a string")
-                    })?
-                    .to_string()
-            };
-
-            let disabled = if disabled.is_null() {
-                false
-            } else {
-                disabled.as_bool().ok_or_else(|| {
-                    MapError::new(gid, disabled, "disabled must be absent or a boolean")
-                })?
-            };
+        for v in &g.variants {
+            let id = v.id;
+            let nickname = v.nickname.as_ref().unwrap_or(&g.name);
+            if nickname.is_empty() {
+                Err(CatalogError::EmptyNickname(id))?;
+            }
+            // infinite weights turn scores into NaN and can't be written back as JSON
+            if !(v.weight.is_finite() && v.weight >= 0.0) {
+                Err(CatalogError::BadWeight(id, v.weight))?;
+            }
+            if v.tags.iter().any(String::is_empty) {
+                Err(CatalogError::EmptyTag(gid))?;
+            }
 
             let map = Rc::new(Map {
                 id,
                 group: RefCell::new(None),
-                players,
-                mode,
-                nickname,
-                is_gag,
-                disabled,
+                players: v.players,
+                mode: v.mode,
+                nickname: nickname.clone(),
+                is_gag: v.gag,
+                disabled: v.disabled,
+                weight: v.weight,
+                tags: v.tags.clone(),
+                aliases: v.aliases.clone(),
             });
 
             group.variants.push(map.clone());
             let existing = maps.insert(id, map);
             if existing.is_some() {
-                Err(MapError::new(gid, &((id as i32).into()), "Dulicate map id"))?;
+                Err(CatalogError::DuplicateId(id))?;
             }
         }
 
         let group = Rc::new(group);
         let existing = groups.insert(gid, group.clone());
         if existing.is_some() {
-            Err(GroupError::new(
-                &((gid as i32).into()),
-                "Dulicate group gid",
-            ))?;
+            Err(CatalogError::DuplicateGid(gid))?;
         }
 
         for v in &group.variants {
@@ -312,5 +346,217 @@ pub fn load_map_data() -> Result<(Groups, Maps), Box<dyn Error>> {
         }
     }
 
+    // an id means one thing forever, whether it's live, an alias or retired
+    let mut seen = HashSet::new();
+    let live = catalog.variants().map(|v| v.id);
+    let aliases = catalog.variants().flat_map(|v| v.aliases.iter().copied());
+    let retired = catalog
+        .groups
+        .iter()
+        .flat_map(|g| g.retired.iter().copied());
+    for id in live.chain(aliases).chain(retired) {
+        if !seen.insert(id) {
+            Err(CatalogError::ReusedId(id))?;
+        }
+    }
+
+    // similar groups may be listed before the groups they name, and similarity goes both ways
+    for g in &catalog.groups {
+        for other in &g.similar {
+            let (a, b) = (g.gid, *other);
+            let ga = groups.get(&a).unwrap();
+            let gb = groups.get(&b).ok_or(CatalogError::UnknownSimilar(a, b))?;
+            if a == b {
+                continue;
+            }
+            ga.similar.borrow_mut().push(b);
+            gb.similar.borrow_mut().push(a);
+        }
+    }
+
     Ok((groups, maps))
 }
+
+#[derive(thiserror::Error, Debug)]
+#[error("{0}: {2}: {1}")]
+pub struct ProfileError(String, JsonValue, String);
+
+impl ProfileError {
+    fn new(path: &str, j: &JsonValue, err: &str) -> Self {
+        ProfileError(path.to_string(), j.clone(), err.to_string())
+    }
+}
+
+pub struct Profile {
+    pub weights: HashMap<u16, f64>,
+    pub scoring: ScoringParams,
+}
+
+/// Load a profile file, all sections are optional:
+/// `{ "weights": { "12": 2.0, "9": 0 }, "scoring": { "age_pow": 0.4 } }`
+pub fn load_profile(path: &str, maps: &Maps) -> Result<Profile, Box<dyn Error>> {
+    let raw_json = fs::read_to_string(path)?;
+    let json = json::parse(&raw_json)?;
+
+    let mut profile = Profile {
+        weights: HashMap::new(),
+        scoring: ScoringParams::default(),
+    };
+
+    let w = &json["weights"];
+    if !w.is_null() && !w.is_object() {
+        Err(ProfileError::new(path, w, "weights must be an object"))?;
+    }
+
+    for (id, weight) in w.entries() {
+        let id = id
+            .parse::<u16>()
+            .ok()
+            .filter(|id| maps.contains_key(id))
+            .ok_or_else(|| ProfileError::new(path, &id.into(), "unknown map id"))?;
+        let weight = weight.as_f64().filter(|w| *w >= 0.0).ok_or_else(|| {
+            ProfileError::new(path, weight, "weight must be a non-negative number")
+        })?;
+
+        profile.weights.insert(id, weight);
+    }
+
+    let scoring = &json["scoring"];
+    if !scoring.is_null() && !scoring.is_object() {
+        Err(ProfileError::new(
+            path,
+            scoring,
+            "scoring must be an object",
+        ))?;
+    }
+
+    let params = &mut profile.scoring;
+    for (key, v) in scoring.entries() {
+        let field = match key {
+            "round_discount" => &mut params.round_discount,
+            "cross_type_round_discount" => &mut params.cross_type_round_discount,
+            "penalty_nonlinearity" => &mut params.penalty_nonlinearity,
+            "age_pow" => &mut params.age_pow,
+            "similar_group_factor" => &mut params.similar_group_factor,
+            _ => Err(ProfileError::new(
+                path,
+                &key.into(),
+                "unknown scoring parameter",
+            ))?,
+        };
+        *field = v.as_f64().filter(|v| *v > 0.0).ok_or_else(|| {
+            ProfileError::new(path, v, "scoring parameters must be positive numbers")
+        })?;
+    }
+
+    Ok(profile)
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("{0}: pool {1}: {3}: {2}")]
+pub struct PoolError(String, String, JsonValue, String);
+
+impl PoolError {
+    fn new(path: &str, pool: &str, j: &JsonValue, err: &str) -> Self {
+        PoolError(
+            path.to_string(),
+            pool.to_string(),
+            j.clone(),
+            err.to_string(),
+        )
+    }
+}
+
+pub static POOLS_FILE: &str = "pools.json";
+
+/// Load a list of pools, each with map ids, a tag query or both, and optional dates:
+/// `[{ "name": "Halloween", "maps": [3, 47], "tags": "spooky -water", "start": "2026-10-20", "end": "2026-11-02" }]`
+pub fn load_pools(path: &str, maps: &Maps) -> Result<Vec<Pool>, Box<dyn Error>> {
+    let raw_json = fs::read_to_string(path)?;
+    let json = json::parse(&raw_json)?;
+
+    if !json.is_array() {
+        Err(PoolError::new(path, "", &json, "pools must be a list"))?;
+    }
+
+    let mut pools: Vec<Pool> = Vec::new();
+    for p in json.members() {
+        let name = p["name"]
+            .as_str()
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| PoolError::new(path, "", p, "pool must have a name"))?;
+        if pools.iter().any(|o| o.name.eq_ignore_ascii_case(name)) {
+            Err(PoolError::new(
+                path,
+                name,
+                &p["name"],
+                "duplicate pool name",
+            ))?;
+        }
+
+        let ids = &p["maps"];
+        if !ids.is_null() && !ids.is_array() {
+            Err(PoolError::new(
+                path,
+                name,
+                ids,
+                "maps must be absent or a list",
+            ))?;
+        }
+        let ids = ids
+            .members()
+            .map(|id| {
+                id.as_u16()
+                    .filter(|id| maps.contains_key(id))
+                    .ok_or_else(|| PoolError::new(path, name, id, "unknown map id"))
+            })
+            .collect::<Result<HashSet<u16>, PoolError>>()?;
+
+        let tags = &p["tags"];
+        if !tags.is_null() && !tags.is_string() {
+            Err(PoolError::new(
+                path,
+                name,
+                tags,
+                "tags must be absent or a string",
+            ))?;
+        }
+        let tags = TagFilter::parse(tags.as_str().unwrap_or_default());
+        if ids.is_empty() && tags.is_empty() {
+            Err(PoolError::new(path, name, p, "pool needs maps or tags"))?;
+        }
+
+        let date = |key: &str| -> Result<Option<NaiveDate>, PoolError> {
+            let d = &p[key];
+            if d.is_null() {
+                return Ok(None);
+            }
+            d.as_str()
+                .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+                .map(Some)
+                .ok_or_else(|| PoolError::new(path, name, d, "dates must look like 2026-10-31"))
+        };
+        let start = date("start")?;
+        let end = date("end")?;
+        if let (Some(s), Some(e)) = (start, end) {
+            if e < s {
+                Err(PoolError::new(
+                    path,
+                    name,
+                    &p["end"],
+                    "pool ends before it starts",
+                ))?;
+            }
+        }
+
+        pools.push(Pool {
+            name: name.to_string(),
+            ids,
+            tags,
+            start,
+            end,
+        });
+    }
+
+    Ok(pools)
+}
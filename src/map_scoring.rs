@@ -1,6 +1,13 @@
-use std::rc::Rc;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use chrono::NaiveDate;
 
 use crate::map_data::{Map, Mode};
+use crate::play_log::{LogEntry, Origin};
 
 static MAX_AGE: u16 = 200;
 static ROUND_PENALTY: f64 = 1000.0; // used during inversion of the score
@@ -9,47 +16,239 @@ static CROSS_TYPE_ROUND_DISCOUNT: f64 = 1.0 / 1.059_463_094_359_295_3; // ~ 12th
 
 static PENALTY_NONLINEARITY: f64 = 1.4; // penalty raised to this power before inverting
 static AGE_POW: f64 = 0.4; // age raised to this power before being multiplied by the inverted penalty
+static SIMILAR_GROUP_FACTOR: f64 = 0.25; // share of the group penalty given to maps in similar groups
+
+/// The tunable constants of the scoring, defaulting to the ones above
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoringParams {
+    pub round_discount: f64,
+    pub cross_type_round_discount: f64,
+    pub penalty_nonlinearity: f64,
+    pub age_pow: f64,
+    pub similar_group_factor: f64,
+}
+
+impl Default for ScoringParams {
+    fn default() -> Self {
+        ScoringParams {
+            round_discount: ROUND_DISCOUNT,
+            cross_type_round_discount: CROSS_TYPE_ROUND_DISCOUNT,
+            penalty_nonlinearity: PENALTY_NONLINEARITY,
+            age_pow: AGE_POW,
+            similar_group_factor: SIMILAR_GROUP_FACTOR,
+        }
+    }
+}
+
+impl ScoringParams {
+    /// the discount that halves a penalty every `rounds` rounds
+    pub fn discount_for_half_life(rounds: f64) -> f64 {
+        0.5f64.powf(1.0 / rounds)
+    }
 
+    pub fn half_life(discount: f64) -> f64 {
+        0.5f64.ln() / discount.ln()
+    }
+}
+
+pub static FAVORITE_WEIGHT: f64 = 3.0; // how much more often maps favorited during a session come up
+
+/// Per-map weights layered over the catalog's `weight`, the session's choices win over the profile's
+#[derive(Default)]
+pub struct WeightOverrides {
+    pub profile: HashMap<u16, f64>,
+    pub session: HashMap<u16, f64>,
+}
+
+impl WeightOverrides {
+    pub fn weight(&self, map: &Map) -> f64 {
+        self.session
+            .get(&map.id)
+            .copied()
+            .unwrap_or_else(|| self.base_weight(map))
+    }
+
+    /// the weight before the session's choices
+    fn base_weight(&self, map: &Map) -> f64 {
+        self.profile.get(&map.id).copied().unwrap_or(map.weight)
+    }
+
+    /// Raise the map over its profile or catalog weight, favoriting again doesn't raise it further
+    pub fn favorite(&mut self, map: &Map) {
+        self.session
+            .insert(map.id, self.base_weight(map) * FAVORITE_WEIGHT);
+    }
+
+    pub fn ban(&mut self, map: &Map) {
+        self.session.insert(map.id, 0.0);
+    }
+}
+
+/// Tags a map must all have and tags it must not have to be offered
+#[derive(Default, Clone)]
+pub struct TagFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn allows(&self, map: &Map) -> bool {
+        self.include.iter().all(|t| map.has_tag(t)) && !self.exclude.iter().any(|t| map.has_tag(t))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Parse filters like "small -water", words starting with '-' are excluded, others included
+    /// with an optional leading '+'
+    pub fn parse(filters: &str) -> Self {
+        let mut filter = TagFilter::default();
+        for word in filters.split_whitespace() {
+            if let Some(tag) = word.strip_prefix('-') {
+                filter.exclude.push(tag.to_string());
+            } else {
+                filter
+                    .include
+                    .push(word.trim_start_matches('+').to_string());
+            }
+        }
+        filter.include.retain(|t| !t.is_empty());
+        filter.exclude.retain(|t| !t.is_empty());
+        filter
+    }
+}
+
+impl std::fmt::Display for TagFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let include = self.include.iter().map(|t| format!("+{}", t));
+        let exclude = self.exclude.iter().map(|t| format!("-{}", t));
+        let all: Vec<String> = include.chain(exclude).collect();
+        all.join(" ").fmt(f)
+    }
+}
+
+/// A named subset of the maps, for events and seasons
+#[derive(Clone)]
+pub struct Pool {
+    pub name: String,
+    pub ids: HashSet<u16>,
+    /// maps matching these are in the pool as well as those listed by id
+    pub tags: TagFilter,
+    /// first and last day the pool is active on its own, both inclusive
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+impl Pool {
+    pub fn contains(&self, map: &Map) -> bool {
+        self.ids.contains(&map.id) || (!self.tags.is_empty() && self.tags.allows(map))
+    }
+
+    /// Only pools with dates activate themselves
+    pub fn is_active(&self, today: NaiveDate) -> bool {
+        (self.start.is_some() || self.end.is_some())
+            && self.start.is_none_or(|s| s <= today)
+            && self.end.is_none_or(|e| today <= e)
+    }
+
+    pub fn active(pools: &[Pool], today: NaiveDate) -> Option<&Pool> {
+        pools.iter().find(|p| p.is_active(today))
+    }
+}
+
+/// Everything besides the log and the lobby's mode and size that shapes the scores
+#[derive(Default)]
+pub struct ScoringConfig {
+    pub weights: WeightOverrides,
+    /// the players in the room, logged with each play
+    pub roster: Vec<String>,
+    /// score from the history of each player on the roster instead of the lobby's
+    pub per_player: bool,
+    pub params: ScoringParams,
+    pub tags: TagFilter,
+    /// only offer maps from this pool
+    pub pool: Option<Pool>,
+}
+
+/// Was the player there for this play? Plays logged without a roster count for everyone. Names
+/// match in any case, by the same rule that keys each player's scoring in [`ScoringState`].
+fn was_present(entry: &LogEntry, player: &str) -> bool {
+    let player = player.to_lowercase();
+    entry.roster.is_empty() || entry.roster.iter().any(|p| p.to_lowercase() == player)
+}
+
+#[derive(Clone)]
 pub struct MapScoring {
     pub map: Rc<Map>,
     pub age: u16,
     pub cross_type_sibling_penalty: f64,
     pub penalty: f64,
+    pub weight: f64,
 }
 
 impl MapScoring {
-    fn map_played(&mut self, other_map: &Map) {
-        self.penalty *= ROUND_DISCOUNT;
-        self.cross_type_sibling_penalty *= CROSS_TYPE_ROUND_DISCOUNT;
-        self.age = MAX_AGE.min(self.age + 1);
-
-        if *other_map == *self.map {
-            self.age = 1;
+    fn new(map: &Rc<Map>, weight: f64) -> Self {
+        MapScoring {
+            map: map.clone(),
+            age: MAX_AGE,
+            cross_type_sibling_penalty: 1.0,
+            penalty: 1.0,
+            weight,
         }
+    }
+
+    fn map_played(&mut self, entry: &LogEntry, params: &ScoringParams) {
+        let other_map = &*entry.map;
+
+        // plays in other lobbies only add a weighted penalty, the clock only advances for our own rounds
+        let weight = match entry.origin {
+            Origin::Local => {
+                self.penalty *= params.round_discount;
+                self.cross_type_sibling_penalty *= params.cross_type_round_discount;
+                self.age = MAX_AGE.min(self.age + 1);
+
+                if *other_map == *self.map {
+                    self.age = 1;
+                }
+
+                1.0
+            }
+            Origin::Foreign(weight) => weight,
+        };
 
         let my_g = self.map.group();
         let other_g = other_map.group();
 
-        if my_g == other_g {
-            if self.map.mode == other_map.mode {
-                self.penalty += ROUND_PENALTY;
-            } else {
-                // we are in a group with the other map, apply a recent-ness penalty, discounted by type
-                self.cross_type_sibling_penalty +=
-                    self.map.mode.mode_discount(other_map.mode) * ROUND_PENALTY;
-            }
+        // maps in similar groups get a share of the penalty a sibling would
+        let weight = if my_g == other_g {
+            weight
+        } else if my_g.is_similar(&other_g) {
+            weight * params.similar_group_factor
+        } else {
+            return;
+        };
+
+        if self.map.mode == other_map.mode {
+            self.penalty += weight * ROUND_PENALTY;
+        } else {
+            // we share (or resemble) the group of the other map, apply a recent-ness penalty, discounted by type
+            self.cross_type_sibling_penalty +=
+                weight * self.map.mode.mode_discount(other_map.mode) * ROUND_PENALTY;
         }
     }
 
-    fn final_score(self) -> (f64, Rc<Map>) {
+    fn final_score(self, params: &ScoringParams) -> (f64, Rc<Map>) {
         // penalty is the sum of both types
         let s = self.penalty + self.cross_type_sibling_penalty;
         // make the penalty non-linear to further penalize recent plays & invert
-        let s = 1000. / s.powf(PENALTY_NONLINEARITY);
+        let s = 1000. / s.powf(params.penalty_nonlinearity);
         // raise the chance of maps that haven't been played in a while
-        let s = s * (self.age as f64).powf(AGE_POW);
+        let s = s * (self.age as f64).powf(params.age_pow);
         // don't let the values go TOO sideways
         let s = s.clamp(0.001, 100000.);
+        // favorites come up more often, and disfavored maps less
+        let s = s * self.weight;
 
         assert!(!s.is_nan(), "Score was NaN, this should not be possible");
 
@@ -62,35 +261,123 @@ fn normalize_scores(scores: &[(f64, Rc<Map>)]) -> Vec<(f64, Rc<Map>)> {
     scores.iter().map(|(s, m)| (s / sum, m.clone())).collect()
 }
 
-fn get_appropriate_maps(mode: Mode, players: u16, all_maps: &[Rc<Map>]) -> Vec<MapScoring> {
+fn get_appropriate_maps(
+    mode: Mode,
+    players: u16,
+    all_maps: &[Rc<Map>],
+    config: &ScoringConfig,
+) -> Vec<MapScoring> {
     all_maps
         .iter()
         // only choose maps that are the correct mode and have enough player capacity
         .filter(|m| m.mode == mode && m.players >= players)
-        .map(|map| MapScoring {
-            map: map.clone(),
-            age: MAX_AGE,
-            cross_type_sibling_penalty: 1.0,
-            penalty: 1.0,
-        })
+        // and that fit the tags and pool asked for
+        .filter(|m| config.tags.allows(m))
+        .filter(|m| config.pool.as_ref().is_none_or(|p| p.contains(m)))
+        .map(|map| MapScoring::new(map, config.weights.weight(map)))
+        // banned maps are never offered
+        .filter(|s| s.weight > 0.0)
         .collect()
 }
 
-pub fn build_scores(
-    log: &[Rc<Map>],
-    mode: Mode,
-    players: u16,
-    all_maps: &[Rc<Map>],
-) -> Vec<(f64, Rc<Map>)> {
-    let mut scores = get_appropriate_maps(mode, players, all_maps);
+/// The scoring of every map after a history of plays, kept up to date as plays are added so picking
+/// doesn't need to replay the whole log
+pub struct ScoringState {
+    params: ScoringParams,
+    log: Vec<LogEntry>,
+    maps: HashMap<u16, MapScoring>,
+    /// the same for each player's own history, by lower-case name, built the first time they're scored
+    players: RefCell<HashMap<String, HashMap<u16, MapScoring>>>,
+}
+
+impl ScoringState {
+    pub fn new(all_maps: &[Rc<Map>], params: &ScoringParams) -> Self {
+        ScoringState {
+            params: *params,
+            log: Vec::new(),
+            maps: all_maps
+                .iter()
+                .map(|m| (m.id, MapScoring::new(m, 1.0)))
+                .collect(),
+            players: RefCell::new(HashMap::new()),
+        }
+    }
 
-    // let every valid map see the log to accunulate penalties and age
-    for s in &mut scores {
-        for l in log {
-            s.map_played(l);
+    /// Rebuild the state by playing through a log
+    pub fn from_log(log: Vec<LogEntry>, all_maps: &[Rc<Map>], params: &ScoringParams) -> Self {
+        let mut state = ScoringState::new(all_maps, params);
+        for entry in log {
+            state.push(entry);
         }
+        state
     }
 
+    pub fn push(&mut self, entry: LogEntry) {
+        for s in self.maps.values_mut() {
+            s.map_played(&entry, &self.params);
+        }
+        for (player, maps) in self.players.get_mut() {
+            if was_present(&entry, player) {
+                for s in maps.values_mut() {
+                    s.map_played(&entry, &self.params);
+                }
+            }
+        }
+        self.log.push(entry);
+    }
+
+    /// Raw scores of the candidates from the plays `player` was present for, replaying their
+    /// history only the first time they're asked for
+    fn player_scores(&self, player: &str, candidates: Vec<MapScoring>) -> Vec<(f64, Rc<Map>)> {
+        let mut players = self.players.borrow_mut();
+        let maps = players.entry(player.to_lowercase()).or_insert_with(|| {
+            let mut maps: HashMap<u16, MapScoring> = self
+                .maps
+                .values()
+                .map(|s| (s.map.id, MapScoring::new(&s.map, 1.0)))
+                .collect();
+            for entry in self.log.iter().filter(|e| was_present(e, player)) {
+                for s in maps.values_mut() {
+                    s.map_played(entry, &self.params);
+                }
+            }
+            maps
+        });
+        score_table(maps, candidates, &self.params)
+    }
+
+    pub fn log(&self) -> &[LogEntry] {
+        &self.log
+    }
+
+    pub fn into_log(self) -> Vec<LogEntry> {
+        self.log
+    }
+}
+
+/// Raw scores of the candidates from the state, the same as letting each of them see the state's log
+fn score_state(
+    state: &ScoringState,
+    candidates: Vec<MapScoring>,
+    params: &ScoringParams,
+) -> Vec<(f64, Rc<Map>)> {
+    score_table(&state.maps, candidates, params)
+}
+
+/// Raw scores of the candidates, taking their penalties and ages from a table kept up to date
+fn score_table(
+    table: &HashMap<u16, MapScoring>,
+    candidates: Vec<MapScoring>,
+    params: &ScoringParams,
+) -> Vec<(f64, Rc<Map>)> {
+    let scores: Vec<MapScoring> = candidates
+        .into_iter()
+        .map(|c| MapScoring {
+            weight: c.weight,
+            ..table[&c.map.id].clone()
+        })
+        .collect();
+
     #[cfg(feature = "debug_raw_scores")]
     {
         for s in &scores {
@@ -98,8 +385,51 @@ pub fn build_scores(
         }
     }
 
-    // turn the map scores into usable numeric scores
-    let scores: Vec<(f64, Rc<Map>)> = scores.into_iter().map(MapScoring::final_score).collect();
+    scores.into_iter().map(|s| s.final_score(params)).collect()
+}
+
+/// The weight of every map that can be offered for the mode and player count
+pub fn candidate_weights(
+    mode: Mode,
+    players: u16,
+    all_maps: &[Rc<Map>],
+    config: &ScoringConfig,
+) -> HashMap<u16, f64> {
+    get_appropriate_maps(mode, players, all_maps, config)
+        .into_iter()
+        .map(|s| (s.map.id, s.weight))
+        .collect()
+}
+
+pub fn build_scores(
+    state: &ScoringState,
+    mode: Mode,
+    players: u16,
+    all_maps: &[Rc<Map>],
+    config: &ScoringConfig,
+) -> Vec<(f64, Rc<Map>)> {
+    debug_assert_eq!(state.params, config.params);
+    let candidates = get_appropriate_maps(mode, players, all_maps, config);
+
+    let scores = if config.per_player && !config.roster.is_empty() {
+        // each player sees only the plays they were present for
+        let per_player: Vec<Vec<(f64, Rc<Map>)>> = config
+            .roster
+            .iter()
+            .map(|p| state.player_scores(p, candidates.clone()))
+            .collect();
+
+        // combine with a geometric mean, so a map any one player is tired of is held back
+        let n = per_player.len() as f64;
+        (0..candidates.len())
+            .map(|i| {
+                let log_sum: f64 = per_player.iter().map(|scores| scores[i].0.ln()).sum();
+                ((log_sum / n).exp(), candidates[i].map.clone())
+            })
+            .collect()
+    } else {
+        score_state(state, candidates, &config.params)
+    };
 
     // normalize the scores so that all the scores add up to 1 (so we can show the user a %)
     let mut scores = normalize_scores(&scores);
@@ -117,3 +447,93 @@ pub fn build_scores(
 
     scores
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::map_data::load_map_data;
+
+    /// The scoring before it was kept incrementally: every candidate sees the whole history
+    fn score_history<'a, I>(
+        history: I,
+        mut scores: Vec<MapScoring>,
+        params: &ScoringParams,
+    ) -> Vec<(f64, Rc<Map>)>
+    where
+        I: Iterator<Item = &'a LogEntry> + Clone,
+    {
+        // let every valid map see the log to accunulate penalties and age
+        for s in &mut scores {
+            for l in history.clone() {
+                s.map_played(l, params);
+            }
+        }
+
+        // turn the map scores into usable numeric scores
+        scores.into_iter().map(|s| s.final_score(params)).collect()
+    }
+
+    #[test]
+    fn incremental_state_matches_replay() {
+        let (_, maps) = load_map_data(&Catalog::default_path()).unwrap();
+        let mut all_maps: Vec<Rc<Map>> = maps.values().cloned().collect();
+        all_maps.sort_by_key(|m| m.id);
+
+        let params = ScoringParams::default();
+        let mut state = ScoringState::new(&all_maps, &params);
+
+        // a deterministic mix of local and foreign plays, long enough for ages to cap out, some
+        // with only one of two players present
+        for i in 0..500 {
+            let map = all_maps[(i * 37 + i / 7) % all_maps.len()].clone();
+            let mut entry = LogEntry::new(map, None);
+            if i % 5 == 0 {
+                entry.origin = Origin::Foreign(0.5);
+            }
+            match i % 4 {
+                0 => entry.roster = vec!["Ann".to_string()],
+                1 => entry.roster = vec!["Bob".to_string()],
+                2 => entry.roster = vec!["Åsa".to_string(), "Ann".to_string()],
+                _ => {}
+            }
+            state.push(entry);
+
+            if i % 50 != 49 {
+                continue;
+            }
+
+            for mode in Mode::ordered() {
+                let candidates = get_appropriate_maps(mode, 0, &all_maps, &Default::default());
+                let replayed = score_history(state.log().iter(), candidates.clone(), &params);
+                let incremental = score_state(&state, candidates.clone(), &params);
+
+                for ((r, rm), (s, sm)) in replayed.iter().zip(&incremental) {
+                    assert_eq!(rm.id, sm.id);
+                    assert_eq!(r, s, "{} after {} plays", rm.map_info(), i + 1);
+                }
+
+                // asked for in another case than logged, outside ASCII too
+                for (asked, logged) in [("ann", "Ann"), ("åsa", "Åsa")] {
+                    let history = state
+                        .log()
+                        .iter()
+                        .filter(|e| e.roster.is_empty() || e.roster.iter().any(|p| p == logged));
+                    let replayed = score_history(history, candidates.clone(), &params);
+                    let incremental = state.player_scores(asked, candidates.clone());
+                    for ((r, rm), (s, sm)) in replayed.iter().zip(&incremental) {
+                        assert_eq!(rm.id, sm.id);
+                        assert_eq!(
+                            r,
+                            s,
+                            "{} for {} after {} plays",
+                            rm.map_info(),
+                            asked,
+                            i + 1
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
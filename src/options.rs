@@ -0,0 +1,290 @@
+use chrono::NaiveDate;
+
+use crate::export::ExportFilter;
+use crate::map_data::Mode;
+use crate::map_scoring::TagFilter;
+use crate::sampling::{Diversity, Sampler};
+use crate::simulation::Simulation;
+use crate::voting::VoteMethod;
+
+#[derive(thiserror::Error, Debug)]
+pub enum OptionsError {
+    #[error("Unknown argument '{0}'")]
+    UnknownArgument(String),
+    #[error("Argument '{0}' needs a value")]
+    MissingValue(String),
+    #[error("Bad value for '{0}': '{1}'")]
+    BadValue(String, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+pub struct Options {
+    pub simulate: bool,
+    pub simulation: Simulation,
+    /// independent simulations to run and aggregate
+    pub trials: usize,
+    pub threads: Option<usize>,
+    /// search for better scoring parameters by simulation
+    pub tune: bool,
+    /// random parameter sets to try instead of the grid
+    pub tune_samples: Option<usize>,
+    /// report what has been played instead of picking maps
+    pub stats: bool,
+    /// list the lines of the log that can't be used and exit
+    pub check_log: bool,
+    /// rewrite the log without the lines that can't be used, keeping a backup
+    pub repair: bool,
+    /// move all but this many of the latest plays from the log to the archive
+    pub compact: Option<usize>,
+    /// how far compacting may change a map's chance of being offered, in percentage points
+    pub tolerance: f64,
+    /// CSV file of plays to merge into the log
+    pub import: Option<String>,
+    /// file to write the play history to, as CSV or HTML
+    pub export: Option<String>,
+    /// which plays to export
+    pub export_filter: ExportFilter,
+    /// read log lines whose id doesn't match their nickname and mode by the nickname and mode
+    pub resolve_by_name: bool,
+    /// print the JSON Schema of the map catalog and exit
+    pub catalog_schema: bool,
+    /// map catalog to load instead of the first all_maps file found
+    pub catalog: Option<String>,
+    /// add, change and remove maps in the catalog from the console
+    pub edit_catalog: bool,
+    /// write the catalog to this file, in the format of its extension, and exit
+    pub convert: Option<String>,
+    pub format: OutputFormat,
+    /// logs from other lobbies whose plays also penalize maps in this one
+    pub foreign_logs: Vec<String>,
+    /// how much a play in a foreign log counts compared to a local one
+    pub foreign_weight: f64,
+    /// profile file with per-map weight overrides
+    pub profile: Option<String>,
+    /// how votes on the offered choices are tallied
+    pub vote_method: VoteMethod,
+    /// file listing the players in the room, one per line
+    pub roster: Option<String>,
+    /// score maps from each present player's history
+    pub per_player: bool,
+    /// how the offered choices are drawn
+    pub sampler: Sampler,
+    /// rules on which maps may be offered together
+    pub diversity: Diversity,
+    /// tags maps must have or must not have to be offered
+    pub tags: TagFilter,
+    /// file of named map pools, instead of pools.json
+    pub pools: Option<String>,
+    /// pool to offer maps from instead of the one active today
+    pub pool: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            simulate: false,
+            simulation: Simulation::default(),
+            trials: 1,
+            threads: None,
+            tune: false,
+            tune_samples: None,
+            stats: false,
+            catalog_schema: false,
+            check_log: false,
+            repair: false,
+            resolve_by_name: false,
+            compact: None,
+            tolerance: 0.1,
+            import: None,
+            export: None,
+            export_filter: ExportFilter::default(),
+            catalog: None,
+            convert: None,
+            edit_catalog: false,
+            format: OutputFormat::Table,
+            foreign_logs: Vec::new(),
+            foreign_weight: 0.5,
+            profile: None,
+            vote_method: VoteMethod::Plurality,
+            roster: None,
+            per_player: false,
+            sampler: Sampler::Sequential,
+            diversity: Diversity::default(),
+            tags: TagFilter::default(),
+            pools: None,
+            pool: None,
+        }
+    }
+}
+
+fn value<I>(arg: &str, args: &mut I) -> Result<String, OptionsError>
+where
+    I: Iterator<Item = String>,
+{
+    args.next()
+        .ok_or_else(|| OptionsError::MissingValue(arg.to_string()))
+}
+
+impl Options {
+    pub fn parse<I>(args: I) -> Result<Self, OptionsError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--simulate" => options.simulate = true,
+                "--rounds" => {
+                    let v = value(&arg, &mut args)?;
+                    options.simulation.rounds =
+                        v.parse().map_err(|_| OptionsError::BadValue(arg, v))?;
+                }
+                "--trials" => {
+                    let v = value(&arg, &mut args)?;
+                    options.trials = v
+                        .parse()
+                        .ok()
+                        .filter(|t| *t > 0)
+                        .ok_or(OptionsError::BadValue(arg, v))?;
+                }
+                "--threads" => {
+                    let v = value(&arg, &mut args)?;
+                    options.threads = Some(
+                        v.parse()
+                            .ok()
+                            .filter(|t| *t > 0)
+                            .ok_or(OptionsError::BadValue(arg, v))?,
+                    );
+                }
+                "--players" => {
+                    let v = value(&arg, &mut args)?;
+                    options.simulation.players = v
+                        .parse()
+                        .ok()
+                        .filter(|p| (8..=16).contains(p))
+                        .ok_or(OptionsError::BadValue(arg, v))?;
+                }
+                "--modes" => {
+                    let v = value(&arg, &mut args)?;
+                    let modes: Result<Vec<Mode>, _> =
+                        v.split(',').map(|m| m.trim().try_into()).collect();
+                    options.simulation.rotation = modes
+                        .ok()
+                        .filter(|m| !m.is_empty())
+                        .ok_or(OptionsError::BadValue(arg, v))?;
+                }
+                "--pick" => {
+                    let v = value(&arg, &mut args)?;
+                    options.simulation.policy = v
+                        .as_str()
+                        .try_into()
+                        .map_err(|_| OptionsError::BadValue(arg, v))?;
+                }
+                "--tune" => options.tune = true,
+                "--tune-samples" => {
+                    let v = value(&arg, &mut args)?;
+                    options.tune_samples =
+                        Some(v.parse().map_err(|_| OptionsError::BadValue(arg, v))?);
+                }
+                "--stats" => options.stats = true,
+                "--catalog-schema" => options.catalog_schema = true,
+                "--check-log" => options.check_log = true,
+                "--repair" => options.repair = true,
+                "--resolve-by-name" => options.resolve_by_name = true,
+                "--compact" => {
+                    let v = value(&arg, &mut args)?;
+                    options.compact = Some(v.parse().map_err(|_| OptionsError::BadValue(arg, v))?);
+                }
+                "--tolerance" => {
+                    let v = value(&arg, &mut args)?;
+                    options.tolerance = v
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|t| *t >= 0.0)
+                        .ok_or(OptionsError::BadValue(arg, v))?;
+                }
+                "--import" => options.import = Some(value(&arg, &mut args)?),
+                "--export" => options.export = Some(value(&arg, &mut args)?),
+                "--from" | "--to" => {
+                    let v = value(&arg, &mut args)?;
+                    let date = NaiveDate::parse_from_str(&v, "%Y-%m-%d")
+                        .map_err(|_| OptionsError::BadValue(arg.clone(), v))?;
+                    if arg == "--from" {
+                        options.export_filter.from = Some(date);
+                    } else {
+                        options.export_filter.to = Some(date);
+                    }
+                }
+                "--session" => {
+                    let v = value(&arg, &mut args)?;
+                    options.export_filter.session =
+                        Some(v.parse().map_err(|_| OptionsError::BadValue(arg, v))?);
+                }
+                "--catalog" => options.catalog = Some(value(&arg, &mut args)?),
+                "--edit-catalog" => options.edit_catalog = true,
+                "--convert" => options.convert = Some(value(&arg, &mut args)?),
+                "--format" => {
+                    let v = value(&arg, &mut args)?;
+                    options.format = match v.as_str() {
+                        "table" => OutputFormat::Table,
+                        "csv" => OutputFormat::Csv,
+                        "json" => OutputFormat::Json,
+                        _ => return Err(OptionsError::BadValue(arg, v)),
+                    };
+                }
+                "--foreign-log" => options.foreign_logs.push(value(&arg, &mut args)?),
+                "--foreign-weight" => {
+                    let v = value(&arg, &mut args)?;
+                    options.foreign_weight = v
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|w| (0.0..=1.0).contains(w))
+                        .ok_or(OptionsError::BadValue(arg, v))?;
+                }
+                "--profile" => options.profile = Some(value(&arg, &mut args)?),
+                "--vote-method" => {
+                    let v = value(&arg, &mut args)?;
+                    options.vote_method = v
+                        .as_str()
+                        .try_into()
+                        .map_err(|_| OptionsError::BadValue(arg, v))?;
+                }
+                "--roster" => options.roster = Some(value(&arg, &mut args)?),
+                "--per-player" => options.per_player = true,
+                "--one-per-group" => options.diversity.one_per_group = true,
+                "--one-gag" => options.diversity.one_gag = true,
+                "--fresh-within" => {
+                    let v = value(&arg, &mut args)?;
+                    options.diversity.fresh_within =
+                        Some(v.parse().map_err(|_| OptionsError::BadValue(arg, v))?);
+                }
+                "--sampler" => {
+                    let v = value(&arg, &mut args)?;
+                    options.sampler = v
+                        .as_str()
+                        .try_into()
+                        .map_err(|_| OptionsError::BadValue(arg, v))?;
+                }
+                "--tag" => options.tags.include.push(value(&arg, &mut args)?),
+                "--no-tag" => options.tags.exclude.push(value(&arg, &mut args)?),
+                "--pools" => options.pools = Some(value(&arg, &mut args)?),
+                "--pool" => options.pool = Some(value(&arg, &mut args)?),
+                _ => return Err(OptionsError::UnknownArgument(arg)),
+            }
+        }
+
+        // simulations pick the way the lobby does
+        options.simulation.sampler = options.sampler;
+        options.simulation.diversity = options.diversity.clone();
+
+        Ok(options)
+    }
+}
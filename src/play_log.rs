@@ -1,49 +1,133 @@
 use std::{
+    collections::{HashMap, HashSet},
     error::Error,
-    fs::OpenOptions,
-    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
-    rc::Rc,
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
 };
 
-use chrono::Utc;
-use regex::Regex;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 
-use crate::map_data::{Map, Maps};
+use crate::files::{lock_for, lock_if_present, write_atomic, FileStamp};
+use crate::map_data::{Groups, Maps, Mode, RcMap};
 
-pub fn append_log(map: &Map) -> Result<(), Box<dyn Error>> {
+pub static LOG_FILE: &str = "play_log.txt";
+/// plays moved out of the log by compaction, still read for stats and exports
+pub static ARCHIVE_FILE: &str = "play_log.archive.txt";
+
+/// Where a logged play happened
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    /// played in this lobby
+    Local,
+    /// played in another lobby, counting for the given fraction of a local play
+    Foreign(f64),
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub map: RcMap,
+    pub time: Option<DateTime<Utc>>,
+    pub origin: Origin,
+    /// players present for this play, empty when no roster was kept
+    pub roster: Vec<String>,
+    /// the session this play was logged in, if it was tagged with one
+    pub session: Option<u32>,
+}
+
+impl LogEntry {
+    pub fn new(map: RcMap, time: Option<DateTime<Utc>>) -> Self {
+        LogEntry {
+            map,
+            time,
+            origin: Origin::Local,
+            roster: Vec::new(),
+            session: None,
+        }
+    }
+}
+
+/// Split a comma separated list of player names
+pub fn parse_roster(names: &str) -> Vec<String> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// A play as written to the log, `notes` are written after the entry as `| key: value`
+pub fn log_line(map: &RcMap, time: DateTime<Utc>, notes: &[(&str, String)]) -> String {
+    let notes: String = notes
+        .iter()
+        .map(|(key, value)| format!(" | {}: {}", key, value))
+        .collect();
+
+    format!(
+        "#{} ({}) {} {}{}\n",
+        map.id,
+        time.format("%Y-%m-%d %H:%M Z"),
+        map.nickname,
+        map.mode.name(),
+        notes
+    )
+}
+
+/// Append a play to the log, `notes` are written after the entry as `| key: value`.
+///
+/// The log is locked while writing. `seen` is the log as it was last read, it is updated to include
+/// this play, and the returned flag is set if something else wrote to the log in the meantime.
+pub fn append_log(
+    map: &RcMap,
+    session: u32,
+    roster: &[String],
+    notes: &[(&str, String)],
+    seen: &mut FileStamp,
+) -> Result<(LogEntry, bool), Box<dyn Error>> {
+    // locked before opening, so this is the log as the last writer left it
+    let _lock = lock_for(LOG_FILE, true)?;
     let mut option = OpenOptions::new();
     option.read(true);
     option.append(true);
     option.create(true);
 
-    let mut f = option.open("play_log.txt")?;
+    let mut f = option.open(LOG_FILE)?;
+    let changed = FileStamp::of_file(&f)? != *seen;
 
     let pos = f.seek(SeekFrom::End(0))?;
 
-    let mut last = String::new();
+    let mut last = [0u8];
     if pos > 0 {
         f.seek(SeekFrom::End(-1))?;
-        f.read_to_string(&mut last)?;
+        f.read_exact(&mut last)?;
 
         f.seek(SeekFrom::End(0))?;
 
-        if last != "\n" {
+        if last != *b"\n" {
             f.write_all("\n".as_bytes())?;
         }
     }
 
-    let now = Utc::now();
-    let now = now.format("%Y-%m-%d %H:%M Z").to_string();
+    let time = Utc::now();
 
-    f.write_fmt(format_args!(
-        "#{} ({}) {} {}\n",
-        map.id,
-        now,
-        map.nickname,
-        map.mode.name()
-    ))?;
+    let session_note = ("session", session.to_string());
+    let roster_note = (!roster.is_empty()).then(|| ("roster", roster.join(", ")));
+    let notes: Vec<(&str, String)> = std::iter::once(session_note)
+        .chain(roster_note)
+        .chain(notes.iter().cloned())
+        .collect();
+
+    f.write_all(log_line(map, time, &notes).as_bytes())?;
+
+    f.flush()?;
+    *seen = FileStamp::of_file(&f)?;
 
-    Ok(())
+    let mut entry = LogEntry::new(map.clone(), Some(time));
+    entry.roster = roster.to_vec();
+    entry.session = Some(session);
+    Ok((entry, changed))
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -60,50 +144,572 @@ impl LogError {
     }
 }
 
-pub fn load_log(maps: &Maps) -> Result<Vec<Rc<Map>>, Box<dyn Error>> {
-    let mut option = OpenOptions::new();
-    option.read(true);
-    option.append(true);
-    option.create(true);
+#[derive(thiserror::Error, Debug)]
+#[error("No log at '{0}'")]
+pub struct MissingLog(String);
+
+/// Why a log line doesn't follow `#<id> (<%Y-%m-%d %H:%M> Z) <nickname> <mode>[ | <key>: <value>]*`
+#[derive(thiserror::Error, Debug)]
+pub enum LineError {
+    #[error("line must start with '#' and the map id")]
+    MissingId,
+    #[error("map id '{0}' is not a number from 0 to 65535")]
+    BadId(String),
+    #[error("timestamp '{0}' is not (YYYY-MM-DD HH:MM Z)")]
+    BadTime(String),
+    #[error("missing the nickname and mode")]
+    MissingMode,
+    #[error("unknown mode '{0}'")]
+    UnknownMode(String),
+    #[error("note '{0}' is not 'key: value'")]
+    BadNote(String),
+    #[error("no map with id {0}")]
+    UnknownMap(u16),
+}
+
+/// One line of the log, as written
+#[derive(Debug)]
+pub struct LogLine<'a> {
+    pub id: u16,
+    /// older lines may lack a timestamp, they still count but can't be interleaved
+    pub time: Option<DateTime<Utc>>,
+    pub nickname: &'a str,
+    pub mode: Mode,
+    pub notes: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> LogLine<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, LineError> {
+        let (head, notes) = line.split_once(" | ").unwrap_or((line, ""));
 
-    let f = option.open("play_log.txt")?;
-    let reader = BufReader::new(f);
+        let head = head.strip_prefix('#').ok_or(LineError::MissingId)?;
+        let (id, rest) = head.split_once(' ').ok_or(LineError::MissingMode)?;
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(LineError::BadId(id.to_string()));
+        }
+        let id = id
+            .parse::<u16>()
+            .map_err(|_| LineError::BadId(id.to_string()))?;
+
+        let (time, rest) = match rest.strip_prefix('(') {
+            Some(timed) => {
+                let (time, rest) = timed
+                    .split_once(") ")
+                    .ok_or_else(|| LineError::BadTime(timed.to_string()))?;
+                let parsed = time
+                    .strip_suffix(" Z")
+                    .and_then(|t| NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M").ok())
+                    .ok_or_else(|| LineError::BadTime(time.to_string()))?;
+                (Some(Utc.from_utc_datetime(&parsed)), rest)
+            }
+            None => (None, rest),
+        };
+
+        let (nickname, mode) = rest.rsplit_once(' ').ok_or(LineError::MissingMode)?;
+        if nickname.trim().is_empty() {
+            return Err(LineError::MissingMode);
+        }
+        let mode: Mode = mode
+            .try_into()
+            .map_err(|_| LineError::UnknownMode(mode.to_string()))?;
+
+        let notes = if notes.is_empty() {
+            Vec::new()
+        } else {
+            notes
+                .split(" | ")
+                .map(|note| {
+                    note.split_once(": ")
+                        .filter(|(key, _)| !key.is_empty() && !key.contains(' '))
+                        .ok_or_else(|| LineError::BadNote(note.to_string()))
+                })
+                .collect::<Result<_, _>>()?
+        };
+
+        Ok(LogLine {
+            id,
+            time,
+            nickname,
+            mode,
+            notes,
+        })
+    }
+
+    pub fn note(&self, key: &str) -> Option<&'a str> {
+        self.notes.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+}
+
+/// A line whose nickname and mode don't match the catalog entry for its id
+#[derive(Debug)]
+pub struct Mismatch {
+    pub id: u16,
+    /// nickname and mode as written in the log
+    pub recorded: String,
+    /// the catalog's nickname and mode for the id, None if there is no such map
+    pub catalog: Option<String>,
+    /// the map with the recorded nickname and mode, when resolving by name
+    pub resolved: Option<u16>,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.catalog {
+            Some(catalog) => write!(
+                f,
+                "recorded as '{}' but map {} is '{}'",
+                self.recorded, self.id, catalog
+            )?,
+            None => write!(f, "no map with id {} for '{}'", self.id, self.recorded)?,
+        }
+        if let Some(resolved) = self.resolved {
+            write!(f, ", read as map {}", resolved)?;
+        }
+        Ok(())
+    }
+}
+
+/// What reading a log turned up besides its entries
+#[derive(Default)]
+pub struct LogCheck {
+    /// lines that are malformed or name a map not in the catalog, with their line number
+    pub errors: Vec<(i32, LineError)>,
+    /// lines that disagree with the catalog but were still read
+    pub mismatches: Vec<(i32, Mismatch)>,
+    /// plays of retired maps, skipped
+    pub retired: usize,
+}
+
+/// Every line of a log, which the caller has locked with [`lock_for`]. Only our own log is created
+/// when missing, the logs of other lobbies may be read-only.
+pub fn read_lines(path: &str) -> std::io::Result<Vec<String>> {
+    let f = if path == LOG_FILE {
+        let mut option = OpenOptions::new();
+        option.read(true);
+        option.append(true);
+        option.create(true);
+        option.open(path)?
+    } else {
+        File::open(path)?
+    };
+
+    BufReader::new(f).lines().collect()
+}
+
+/// Read every well-formed play of a known map, collecting what went wrong with the rest.
+///
+/// With `by_name`, a line whose id is unknown or disagrees with its nickname and mode is read
+/// as the map with that nickname and mode instead, if there is exactly one.
+pub fn read_log_file(
+    path: &str,
+    maps: &Maps,
+    groups: &Groups,
+    origin: Origin,
+    by_name: bool,
+) -> Result<(Vec<LogEntry>, LogCheck), Box<dyn Error>> {
+    if path != LOG_FILE && !Path::new(path).exists() {
+        return Err(MissingLog(path.to_string()).into());
+    }
+
+    // shared, so no play is appended halfway through, only our own log gets a lock file
+    let _lock = if path == LOG_FILE {
+        Some(lock_for(path, false)?)
+    } else {
+        lock_if_present(path)
+    };
+    let lines = read_lines(path)?;
+    read_log_lines(lines.into_iter().map(Ok), maps, groups, origin, by_name)
+}
+
+/// `read_log_file` for lines already read, numbered from 1
+pub fn read_log_lines<I>(
+    lines: I,
+    maps: &Maps,
+    groups: &Groups,
+    origin: Origin,
+    by_name: bool,
+) -> Result<(Vec<LogEntry>, LogCheck), Box<dyn Error>>
+where
+    I: IntoIterator<Item = std::io::Result<String>>,
+{
+    // renumbered and removed maps
+    let aliases: HashMap<u16, &RcMap> = maps
+        .values()
+        .flat_map(|m| m.aliases.iter().map(move |a| (*a, m)))
+        .collect();
+    let retired: HashSet<u16> = groups
+        .values()
+        .flat_map(|g| g.retired.iter().copied())
+        .collect();
+
+    let mut names: HashMap<(String, Mode), Vec<&RcMap>> = HashMap::new();
+    for m in maps.values() {
+        names
+            .entry((m.nickname.to_lowercase(), m.mode))
+            .or_default()
+            .push(m);
+    }
 
     let mut records = Vec::new();
+    let mut check = LogCheck::default();
 
-    for (line, line_num) in reader.lines().zip(1..) {
+    for (line, line_num) in lines.into_iter().zip(1..) {
         let line = line?;
         let line = line.trim();
         if line.is_empty() {
             continue; // ignore empty lines
         }
 
-        let re = Regex::new("\\d{1,3}")?;
-        let ma = re.find(line);
-        if ma.is_none() {
-            return Err(Box::new(LogError::new(
-                line_num,
-                "Could not find map id",
-                line,
-            )));
+        let parsed = match LogLine::parse(line) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                check.errors.push((line_num, e));
+                continue;
+            }
+        };
+
+        let id = parsed.id;
+        let named = || {
+            let key = (parsed.nickname.to_lowercase(), parsed.mode);
+            names
+                .get(&key)
+                .filter(|found| found.len() == 1 && by_name)
+                .map(|found| found[0])
+        };
+        let mismatch = |catalog: Option<&RcMap>, resolved: Option<&RcMap>| Mismatch {
+            id,
+            recorded: format!("{} {}", parsed.nickname, parsed.mode.name()),
+            catalog: catalog.map(|m| format!("{} {}", m.nickname, m.mode.name())),
+            resolved: resolved.map(|m| m.id),
+        };
+
+        let map = match maps.get(&id) {
+            Some(map) if map.nickname == parsed.nickname && map.mode == parsed.mode => map,
+            Some(map) => {
+                let resolved = named();
+                check
+                    .mismatches
+                    .push((line_num, mismatch(Some(map), resolved)));
+                resolved.unwrap_or(map)
+            }
+            // aliased ids were renumbered on purpose, their old nickname may differ
+            None if aliases.contains_key(&id) => aliases[&id],
+            None if retired.contains(&id) => {
+                check.retired += 1;
+                continue;
+            }
+            None => match named() {
+                Some(resolved) => {
+                    check
+                        .mismatches
+                        .push((line_num, mismatch(None, Some(resolved))));
+                    resolved
+                }
+                None => {
+                    check.errors.push((line_num, LineError::UnknownMap(id)));
+                    continue;
+                }
+            },
+        };
+
+        let roster = parsed.note("roster").map(parse_roster).unwrap_or_default();
+        let session = parsed.note("session").and_then(|s| s.parse::<u32>().ok());
+
+        records.push(LogEntry {
+            map: map.clone(),
+            time: parsed.time,
+            origin,
+            roster,
+            session,
+        });
+    }
+
+    Ok((records, check))
+}
+
+pub fn load_log(
+    maps: &Maps,
+    groups: &Groups,
+    by_name: bool,
+    quiet: bool,
+) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    load_log_file(LOG_FILE, maps, groups, Origin::Local, by_name, quiet)
+}
+
+/// The plays moved out of the log by compaction, none if it was never compacted
+pub fn load_archive(
+    maps: &Maps,
+    groups: &Groups,
+    by_name: bool,
+) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    if !Path::new(ARCHIVE_FILE).exists() {
+        return Ok(Vec::new());
+    }
+    load_log_file(ARCHIVE_FILE, maps, groups, Origin::Local, by_name, false)
+}
+
+/// The archived plays followed by the log, for looking back over everything played
+pub fn load_history(
+    maps: &Maps,
+    groups: &Groups,
+    by_name: bool,
+) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    let mut history = load_archive(maps, groups, by_name)?;
+    history.extend(load_log(maps, groups, by_name, false)?);
+    Ok(history)
+}
+
+/// Read a log, skipping lines that can't be used with a warning unless `quiet`
+pub fn load_log_file(
+    path: &str,
+    maps: &Maps,
+    groups: &Groups,
+    origin: Origin,
+    by_name: bool,
+    quiet: bool,
+) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    let (records, check) = read_log_file(path, maps, groups, origin, by_name)?;
+    if quiet {
+        return Ok(records);
+    }
+
+    if !check.errors.is_empty() {
+        eprintln!(
+            "{}: skipped {} bad lines, run with --check-log to list them",
+            path,
+            check.errors.len()
+        );
+    }
+    if !check.mismatches.is_empty() {
+        eprintln!(
+            "{}: {} lines don't match the catalog, run with --check-log to list them",
+            path,
+            check.mismatches.len()
+        );
+    }
+    if check.retired > 0 {
+        eprintln!("{}: skipped {} plays of retired maps", path, check.retired);
+    }
+
+    Ok(records)
+}
+
+/// List every line of the log that can't be used or doesn't match the catalog, and why
+pub fn check_log(
+    maps: &Maps,
+    groups: &Groups,
+    by_name: bool,
+) -> Result<Vec<LogError>, Box<dyn Error>> {
+    let lines = {
+        let _lock = lock_for(LOG_FILE, false)?;
+        read_lines(LOG_FILE)?
+    };
+    let (_, check) = read_log_lines(
+        lines.iter().cloned().map(Ok),
+        maps,
+        groups,
+        Origin::Local,
+        by_name,
+    )?;
+    let error =
+        |line_num: i32, e: &dyn Display| LogError::new(line_num, e, &lines[line_num as usize - 1]);
+
+    let mut found: Vec<(i32, LogError)> = check
+        .errors
+        .iter()
+        .map(|(line_num, e)| (*line_num, error(*line_num, e)))
+        .chain(
+            check
+                .mismatches
+                .iter()
+                .map(|(line_num, m)| (*line_num, error(*line_num, m))),
+        )
+        .collect();
+    found.sort_by_key(|(line_num, _)| *line_num);
+
+    Ok(found.into_iter().map(|(_, e)| e).collect())
+}
+
+/// The time and id of every timestamped line of the log and its archive, as written
+pub fn logged_plays() -> std::io::Result<HashSet<(DateTime<Utc>, u16)>> {
+    // compaction moves lines to the archive under the log's lock
+    let _lock = lock_for(LOG_FILE, false)?;
+    let mut lines = read_lines(LOG_FILE)?;
+    if Path::new(ARCHIVE_FILE).exists() {
+        lines.extend(read_lines(ARCHIVE_FILE)?);
+    }
+
+    Ok(lines
+        .iter()
+        .filter_map(|line| LogLine::parse(line.trim()).ok())
+        .filter_map(|line| line.time.map(|time| (time, line.id)))
+        .collect())
+}
+
+/// Copy the log to `play_log.txt.<time>.bak`, numbering the name rather than replacing a backup
+/// made within the same second. Returns the backup's path.
+fn backup_log() -> std::io::Result<String> {
+    let time = Utc::now().format("%Y%m%d%H%M%S");
+    let mut n = 0;
+    loop {
+        let path = match n {
+            0 => format!("{}.{}.bak", LOG_FILE, time),
+            n => format!("{}.{}-{}.bak", LOG_FILE, time, n),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut backup) => {
+                std::io::copy(&mut File::open(LOG_FILE)?, &mut backup)?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Rewrite the log without the lines `check_log` finds, after copying it to a backup.
+/// Returns the number of lines dropped and the backup's path, None if there was nothing to drop.
+pub fn repair_log(
+    maps: &Maps,
+    groups: &Groups,
+    by_name: bool,
+) -> Result<Option<(usize, String)>, Box<dyn Error>> {
+    // hold off appends until the new log is in place
+    let _lock = lock_for(LOG_FILE, true)?;
+    let lines = read_lines(LOG_FILE)?;
+
+    let (_, check) = read_log_lines(
+        lines.iter().cloned().map(Ok),
+        maps,
+        groups,
+        Origin::Local,
+        by_name,
+    )?;
+    let bad: HashSet<i32> = check.errors.iter().map(|(line_num, _)| *line_num).collect();
+    if bad.is_empty() {
+        return Ok(None);
+    }
+
+    let backup = backup_log()?;
+
+    let kept: String = lines
+        .into_iter()
+        .zip(1..)
+        .filter(|(_, line_num)| !bad.contains(line_num))
+        .map(|(line, _)| line + "\n")
+        .collect();
+    write_atomic(LOG_FILE, &kept)?;
+
+    Ok(Some((bad.len(), backup)))
+}
+
+/// Write timestamped lines into the log in time order, after copying it to a backup. Lines already in
+/// the log keep their order, those without a timestamp stay behind the line before them.
+/// Returns the backup's path.
+pub fn merge_into_log(lines: Vec<(DateTime<Utc>, String)>) -> Result<String, Box<dyn Error>> {
+    let _lock = lock_for(LOG_FILE, true)?;
+    let existing = read_lines(LOG_FILE)?;
+    let backup = backup_log()?;
+
+    let mut merged: Vec<(Option<DateTime<Utc>>, String)> = Vec::new();
+    let mut last_time = None;
+    for line in existing {
+        if line.trim().is_empty() {
+            continue;
         }
+        if let Some(time) = LogLine::parse(line.trim()).ok().and_then(|l| l.time) {
+            last_time = Some(time);
+        }
+        merged.push((last_time, line + "\n"));
+    }
+    merged.extend(lines.into_iter().map(|(time, line)| (Some(time), line)));
 
-        let ma = ma.unwrap().as_str().to_string();
-        let id = ma
-            .parse::<u16>()
-            .map_err(|_| LogError::new(line_num, "Could not parse map id", line))?;
-
-        let map = maps.get(&id);
-        if map.is_none() {
-            return Err(Box::new(LogError::new(
-                line_num,
-                "Could not find map with id",
-                id,
-            )));
+    // stable, so lines with equal times stay in the order they came
+    merged.sort_by_key(|(t, _)| *t);
+
+    let contents: String = merged.into_iter().map(|(_, line)| line).collect();
+    write_atomic(LOG_FILE, &contents)?;
+
+    Ok(backup)
+}
+
+/// Interleave the plays of several logs by timestamp.
+///
+/// Entries without a timestamp keep their place behind the entry before them in their own log.
+pub fn merge_logs(logs: Vec<Vec<LogEntry>>) -> Vec<LogEntry> {
+    let mut merged: Vec<(Option<DateTime<Utc>>, LogEntry)> = Vec::new();
+
+    for log in logs {
+        let mut last_time = None;
+        for entry in log {
+            if entry.time.is_some() {
+                last_time = entry.time;
+            }
+            merged.push((last_time, entry));
         }
+    }
+
+    // stable, so entries with equal times stay in log order
+    merged.sort_by_key(|(t, _)| *t);
+
+    merged.into_iter().map(|(_, e)| e).collect()
+}
 
-        records.push(map.unwrap().clone());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_log_lines() {
+        // as every version has written them
+        let line = LogLine::parse("#12 (2024-03-05 19:40 Z) Neden-1 TD").unwrap();
+        assert_eq!(line.id, 12);
+        assert_eq!(
+            line.time,
+            Some(Utc.with_ymd_and_hms(2024, 3, 5, 19, 40, 0).unwrap())
+        );
+        assert_eq!(line.nickname, "Neden-1");
+        assert_eq!(line.mode, Mode::TD);
+        assert!(line.notes.is_empty());
+
+        // hand written, without a timestamp
+        let line = LogLine::parse("#7 Bamboo Forest dm").unwrap();
+        assert_eq!((line.id, line.time), (7, None));
+        assert_eq!((line.nickname, line.mode), ("Bamboo Forest", Mode::DM));
+
+        // ids past three digits, which the old parser cut short
+        assert_eq!(LogLine::parse("#1234 Neden-1 TD").unwrap().id, 1234);
+
+        let line = LogLine::parse(
+            "#9 (2024-03-05 19:40 Z) Neden-1 DM | session: 3 | roster: Ann, Bob | vote: 2-1",
+        )
+        .unwrap();
+        assert_eq!(line.note("session"), Some("3"));
+        assert_eq!(line.note("roster"), Some("Ann, Bob"));
+        assert_eq!(line.note("vote"), Some("2-1"));
+        assert_eq!(line.note("imported"), None);
     }
 
-    Ok(records)
+    #[test]
+    fn rejects_malformed_log_lines() {
+        let err = |line| LogLine::parse(line).unwrap_err();
+
+        // the old parser took the first digits as the id, here the year
+        assert!(matches!(
+            err("2024-03-05 #12 Neden-1 TD"),
+            LineError::MissingId
+        ));
+        assert!(matches!(err("#70000 Neden-1 TD"), LineError::BadId(_)));
+        assert!(matches!(err("#12a Neden-1 TD"), LineError::BadId(_)));
+        assert!(matches!(
+            err("#12 (2024-03-05 19:40) Neden-1 TD"),
+            LineError::BadTime(_)
+        ));
+        assert!(matches!(err("#12 TD"), LineError::MissingMode));
+        assert!(matches!(err("#12 Neden-1 XX"), LineError::UnknownMode(_)));
+        assert!(matches!(
+            err("#12 Neden-1 TD | session 3"),
+            LineError::BadNote(_)
+        ));
+    }
 }
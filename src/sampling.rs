@@ -0,0 +1,438 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    io::{stdout, Write},
+};
+
+use rand::{random, seq::SliceRandom, thread_rng};
+
+use crate::map_data::{Map, RcMap};
+use crate::play_log::{LogEntry, Origin};
+
+pub static CHOICE_CT: usize = 3; // maps offered each round
+static EXACT_LIMIT: f64 = 2_000_000.; // largest number of draw sequences enumerated exactly
+static MONTE_CARLO_DRAWS: usize = 20_000;
+
+/// How the offered choices are drawn from the scored maps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampler {
+    /// one map at a time in proportion to score, removing each as it is drawn
+    Sequential,
+    /// systematic sampling, every map is offered with a chance proportional to its score
+    Systematic,
+}
+
+impl Display for Sampler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sampler::Sequential => "sequential",
+            Sampler::Systematic => "systematic",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown sampler {0}")]
+pub struct UnknownSampler(String);
+
+impl TryInto<Sampler> for &str {
+    type Error = UnknownSampler;
+
+    fn try_into(self) -> Result<Sampler, Self::Error> {
+        match &*self.to_lowercase() {
+            "sequential" => Ok(Sampler::Sequential),
+            "systematic" => Ok(Sampler::Systematic),
+            _ => Err(UnknownSampler(self.to_string())),
+        }
+    }
+}
+
+fn draw_sequential(weights: &[f64], count: usize) -> Vec<usize> {
+    let mut left: Vec<usize> = (0..weights.len()).collect();
+    let mut drawn = Vec::new();
+
+    while drawn.len() < count && !left.is_empty() {
+        let sum: f64 = left.iter().map(|i| weights[*i]).sum();
+        let mut random: f64 = random::<f64>() * sum;
+        let pos = left
+            .iter()
+            .position(|i| {
+                random -= weights[*i];
+                random <= 0.
+            })
+            .unwrap_or(left.len() - 1);
+        drawn.push(left.remove(pos));
+    }
+
+    drawn
+}
+
+/// Chance of each map being offered when drawn sequentially, by enumerating every draw order
+fn sequential_exact(weights: &[f64], count: usize) -> Vec<f64> {
+    fn walk(
+        weights: &[f64],
+        taken: &mut [bool],
+        left_sum: f64,
+        depth: usize,
+        p: f64,
+        out: &mut [f64],
+    ) {
+        for i in 0..weights.len() {
+            if taken[i] {
+                continue;
+            }
+            let p_i = p * weights[i] / left_sum;
+            out[i] += p_i;
+            if depth > 1 {
+                taken[i] = true;
+                walk(weights, taken, left_sum - weights[i], depth - 1, p_i, out);
+                taken[i] = false;
+            }
+        }
+    }
+
+    let mut out = vec![0.; weights.len()];
+    let sum = weights.iter().sum();
+    walk(
+        weights,
+        &mut vec![false; weights.len()],
+        sum,
+        count,
+        1.,
+        &mut out,
+    );
+    out
+}
+
+fn sequential_monte_carlo(weights: &[f64], count: usize) -> Vec<f64> {
+    let mut hits = vec![0u32; weights.len()];
+    for _ in 0..MONTE_CARLO_DRAWS {
+        for i in draw_sequential(weights, count) {
+            hits[i] += 1;
+        }
+    }
+    hits.iter()
+        .map(|h| *h as f64 / MONTE_CARLO_DRAWS as f64)
+        .collect()
+}
+
+/// Chances proportional to weight, summing to `count`, with any map that would go over 1 capped at 1
+fn proportional(weights: &[f64], count: usize) -> Vec<f64> {
+    let mut pi = vec![1.; weights.len()];
+    let mut capped = vec![false; weights.len()];
+
+    loop {
+        let slots = (count - capped.iter().filter(|c| **c).count()) as f64;
+        let free_sum: f64 = (0..weights.len())
+            .filter(|i| !capped[*i])
+            .map(|i| weights[i])
+            .sum();
+
+        let mut changed = false;
+        for i in 0..weights.len() {
+            if capped[i] {
+                continue;
+            }
+            pi[i] = slots * weights[i] / free_sum;
+            if pi[i] >= 1. {
+                pi[i] = 1.;
+                capped[i] = true;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return pi;
+        }
+    }
+}
+
+/// Lay the chances end to end in a random order and take the maps under `count` evenly spaced points
+fn draw_systematic(weights: &[f64], count: usize) -> Vec<usize> {
+    let pi = proportional(weights, count);
+    let mut order: Vec<usize> = (0..weights.len()).collect();
+    order.shuffle(&mut thread_rng());
+
+    let start = random::<f64>();
+    let mut drawn = Vec::new();
+    let mut cumulative = 0.;
+    for i in order {
+        let next = cumulative + pi[i];
+        let point = start + drawn.len() as f64;
+        if point >= cumulative && point < next {
+            drawn.push(i);
+        }
+        cumulative = next;
+    }
+
+    // rounding can leave the last point just past the end
+    while drawn.len() < count {
+        let missing = (0..weights.len())
+            .filter(|i| !drawn.contains(i))
+            .max_by(|a, b| pi[*a].partial_cmp(&pi[*b]).unwrap())
+            .unwrap();
+        drawn.push(missing);
+    }
+
+    drawn
+}
+
+impl Sampler {
+    /// Draw up to `count` different maps, returning their indexes
+    pub fn draw(&self, scores: &[(f64, RcMap)], count: usize) -> Vec<usize> {
+        let weights: Vec<f64> = scores.iter().map(|s| s.0).collect();
+        if weights.len() <= count {
+            return (0..weights.len()).collect();
+        }
+
+        match self {
+            Sampler::Sequential => draw_sequential(&weights, count),
+            Sampler::Systematic => draw_systematic(&weights, count),
+        }
+    }
+
+    /// The chance of each map being among the `count` offered, by map id
+    pub fn inclusion_probabilities(
+        &self,
+        scores: &[(f64, RcMap)],
+        count: usize,
+    ) -> HashMap<u16, f64> {
+        let weights: Vec<f64> = scores.iter().map(|s| s.0).collect();
+
+        let chances = if weights.len() <= count {
+            vec![1.; weights.len()]
+        } else {
+            match self {
+                Sampler::Sequential if (weights.len() as f64).powi(count as i32) <= EXACT_LIMIT => {
+                    sequential_exact(&weights, count)
+                }
+                Sampler::Sequential => sequential_monte_carlo(&weights, count),
+                Sampler::Systematic => proportional(&weights, count),
+            }
+        };
+
+        scores.iter().map(|(_, m)| m.id).zip(chances).collect()
+    }
+}
+
+/// Optional rules on which maps may be offered together
+#[derive(Debug, Clone, Default)]
+pub struct Diversity {
+    /// never offer two variants of the same group
+    pub one_per_group: bool,
+    /// never offer two gag maps
+    pub one_gag: bool,
+    /// offer at least one map that hasn't been played in this many rounds
+    pub fresh_within: Option<usize>,
+}
+
+impl Diversity {
+    fn is_none(&self) -> bool {
+        !self.one_per_group && !self.one_gag && self.fresh_within.is_none()
+    }
+
+    /// Maps played in the last `fresh_within` rounds of this lobby
+    pub fn recent(&self, log: &[LogEntry]) -> HashSet<u16> {
+        let rounds = self.fresh_within.unwrap_or(0);
+        log.iter()
+            .rev()
+            .filter(|e| e.origin == Origin::Local)
+            .take(rounds)
+            .map(|e| e.map.id)
+            .collect()
+    }
+
+    fn conflicts(&self, a: &Map, b: &Map) -> bool {
+        (self.one_per_group && a.group() == b.group()) || (self.one_gag && a.is_gag && b.is_gag)
+    }
+
+    fn is_fresh(&self, map: &Map, recent: &HashSet<u16>) -> bool {
+        !recent.contains(&map.id)
+    }
+
+    fn allows(&self, drawn: &[usize], scores: &[(f64, RcMap)], recent: &HashSet<u16>) -> bool {
+        let maps: Vec<&RcMap> = drawn.iter().map(|i| &scores[*i].1).collect();
+
+        let conflict = maps
+            .iter()
+            .enumerate()
+            .any(|(i, a)| maps[i + 1..].iter().any(|b| self.conflicts(a, b)));
+
+        // only ask for a fresh map if there is one to be had
+        let fresh_wanted =
+            self.fresh_within.is_some() && scores.iter().any(|(_, m)| self.is_fresh(m, recent));
+        let fresh = maps.iter().any(|m| self.is_fresh(m, recent));
+
+        !conflict && (fresh || !fresh_wanted)
+    }
+
+    /// Draw one map at a time, skipping maps that conflict with those already drawn, holding the
+    /// last slot for a fresh map if none came up yet. Rules that can't be met are dropped.
+    fn draw_greedy(
+        &self,
+        weights: &[f64],
+        scores: &[(f64, RcMap)],
+        count: usize,
+        recent: &HashSet<u16>,
+    ) -> Vec<usize> {
+        let mut drawn: Vec<usize> = Vec::new();
+
+        while drawn.len() < count && drawn.len() < weights.len() {
+            let left: Vec<usize> = (0..weights.len()).filter(|i| !drawn.contains(i)).collect();
+            let allowed: Vec<usize> = left
+                .iter()
+                .copied()
+                .filter(|i| {
+                    drawn
+                        .iter()
+                        .all(|d| !self.conflicts(&scores[*d].1, &scores[*i].1))
+                })
+                .collect();
+            let allowed = if allowed.is_empty() { left } else { allowed };
+
+            let needs_fresh = self.fresh_within.is_some()
+                && drawn.len() + 1 == count
+                && !drawn.iter().any(|d| self.is_fresh(&scores[*d].1, recent));
+            let fresh: Vec<usize> = allowed
+                .iter()
+                .copied()
+                .filter(|i| self.is_fresh(&scores[*i].1, recent))
+                .collect();
+            let pool = if needs_fresh && !fresh.is_empty() {
+                fresh
+            } else {
+                allowed
+            };
+
+            let pool_weights: Vec<f64> = pool.iter().map(|i| weights[*i]).collect();
+            let pick = draw_sequential(&pool_weights, 1)[0];
+            drawn.push(pool[pick]);
+        }
+
+        drawn
+    }
+}
+
+static DIVERSE_TRIES: usize = 100; // draws tried before building the choices one at a time instead
+
+impl Sampler {
+    /// Draw up to `count` different maps following the diversity rules, returning their indexes.
+    ///
+    /// Draws that break a rule are redrawn, so the chances shown by [`Sampler::inclusion_probabilities`]
+    /// only hold approximately while rules are in effect.
+    pub fn draw_diverse(
+        &self,
+        scores: &[(f64, RcMap)],
+        count: usize,
+        diversity: &Diversity,
+        recent: &HashSet<u16>,
+    ) -> Vec<usize> {
+        if diversity.is_none() {
+            return self.draw(scores, count);
+        }
+
+        for _ in 0..DIVERSE_TRIES {
+            let drawn = self.draw(scores, count);
+            if diversity.allows(&drawn, scores, recent) {
+                return drawn;
+            }
+        }
+
+        let weights: Vec<f64> = scores.iter().map(|s| s.0).collect();
+        diversity.draw_greedy(&weights, scores, count, recent)
+    }
+}
+
+/// Highest score first
+pub fn sort_score<T>(a: &(f64, T), b: &(f64, T)) -> Ordering {
+    a.0.partial_cmp(&b.0).unwrap().reverse()
+}
+
+/// Draw the choices to offer from the scored maps, highest score first
+pub fn pick_random_maps(
+    scores: Vec<(f64, RcMap)>,
+    sampler: Sampler,
+    diversity: &Diversity,
+    recent: &HashSet<u16>,
+    quiet: bool,
+) -> Result<Vec<(f64, RcMap)>, Box<dyn Error>> {
+    if !quiet {
+        print!("Selecting Options.");
+        stdout().flush()?;
+    }
+
+    let mut random_maps: Vec<(f64, RcMap)> = sampler
+        .draw_diverse(&scores, CHOICE_CT, diversity, recent)
+        .into_iter()
+        .map(|idx| scores[idx].clone())
+        .collect();
+
+    if !quiet {
+        print!("{}", ".".repeat(random_maps.len()));
+        stdout().flush()?;
+    }
+
+    random_maps.sort_unstable_by(sort_score);
+
+    Ok(random_maps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // several standard deviations of a frequency over MONTE_CARLO_DRAWS draws
+    static TOLERANCE: f64 = 0.02;
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        for (i, (x, y)) in a.iter().zip(b).enumerate() {
+            assert!((x - y).abs() < TOLERANCE, "map {}: {} vs {}", i, x, y);
+        }
+    }
+
+    #[test]
+    fn sequential_exact_matches_monte_carlo() {
+        let weights = [5., 3., 1., 1., 0.5];
+        let exact = sequential_exact(&weights, 3);
+        assert!((exact.iter().sum::<f64>() - 3.).abs() < 1e-9);
+        assert_close(&exact, &sequential_monte_carlo(&weights, 3));
+    }
+
+    #[test]
+    fn proportional_sums_to_count_and_caps_at_one() {
+        let pi = proportional(&[10., 1., 1., 1.], 2);
+        assert_eq!(pi[0], 1.);
+        for p in &pi[1..] {
+            assert!((p - 1. / 3.).abs() < 1e-9);
+        }
+
+        let pi = proportional(&[4., 3., 2., 1., 1., 0.5], 3);
+        assert!((pi.iter().sum::<f64>() - 3.).abs() < 1e-9);
+        assert!(pi.iter().all(|p| *p <= 1.));
+    }
+
+    #[test]
+    fn systematic_draws_in_proportion() {
+        let weights = [4., 3., 2., 1., 1., 0.5];
+        let mut hits = vec![0u32; weights.len()];
+        for _ in 0..MONTE_CARLO_DRAWS {
+            let drawn = draw_systematic(&weights, 3);
+            assert_eq!(drawn.len(), 3);
+            assert!(drawn
+                .iter()
+                .all(|i| drawn.iter().filter(|j| *j == i).count() == 1));
+            for i in drawn {
+                hits[i] += 1;
+            }
+        }
+
+        let freq: Vec<f64> = hits
+            .iter()
+            .map(|h| *h as f64 / MONTE_CARLO_DRAWS as f64)
+            .collect();
+        assert_close(&freq, &proportional(&weights, 3));
+    }
+}
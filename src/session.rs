@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::map_data::{Mode, RcMap};
+use crate::play_log::LogEntry;
+
+static SESSION_GAP_HOURS: i64 = 4; // plays further apart than this without a session tag start a new session
+
+fn is_gap(before: Option<DateTime<Utc>>, after: Option<DateTime<Utc>>) -> bool {
+    match (before, after) {
+        (Some(b), Some(a)) => a - b > Duration::hours(SESSION_GAP_HOURS),
+        // without timestamps there is nothing to split on
+        _ => false,
+    }
+}
+
+/// The session of every entry of a local log.
+///
+/// Entries logged with a session keep it, others join the session of the entry before them unless
+/// enough time passed between them to call it a new session. New sessions are numbered past those
+/// seen so far and apart from every session tagged anywhere in the log, so imported or untagged
+/// plays never join a tagged session they weren't part of.
+pub fn label_sessions(log: &[LogEntry]) -> Vec<u32> {
+    let tagged: HashSet<u32> = log.iter().filter_map(|e| e.session).collect();
+    let mut labels = Vec::with_capacity(log.len());
+    let mut max_seen = 0;
+    let mut current = 0;
+    let mut last_time = None;
+
+    for (entry, idx) in log.iter().zip(0..) {
+        current = match entry.session {
+            Some(s) => s,
+            None if idx == 0 || is_gap(last_time, entry.time) => {
+                (max_seen + 1..).find(|s| !tagged.contains(s)).unwrap()
+            }
+            None => current,
+        };
+        max_seen = max_seen.max(current);
+        if entry.time.is_some() {
+            last_time = entry.time;
+        }
+        labels.push(current);
+    }
+
+    labels
+}
+
+pub struct Session {
+    pub id: u32,
+    pub plays: Vec<RcMap>,
+    /// maps played in earlier sessions
+    seen_before: HashSet<u16>,
+    /// the highest session label in the log, tagged or not, new sessions are numbered past it
+    last_label: u32,
+}
+
+impl Session {
+    /// Pick the last session of the log back up if it was recent, otherwise start a new one
+    pub fn resume_or_start(log: &[LogEntry], now: DateTime<Utc>) -> Self {
+        let labels = label_sessions(log);
+
+        let last = log.last().zip(labels.last());
+        let (id, resume) = match last {
+            Some((entry, id)) if !is_gap(entry.time, Some(now)) && entry.time.is_some() => {
+                (*id, true)
+            }
+            Some(_) => (labels.iter().max().unwrap() + 1, false),
+            None => (1, false),
+        };
+
+        let mut session = Session {
+            id,
+            plays: Vec::new(),
+            seen_before: HashSet::new(),
+            last_label: labels.iter().max().copied().unwrap_or(0),
+        };
+        for (entry, label) in log.iter().zip(labels) {
+            if resume && label == id {
+                session.plays.push(entry.map.clone());
+            } else {
+                session.seen_before.insert(entry.map.id);
+            }
+        }
+
+        session
+    }
+
+    /// End this session and start the one after it, numbered past any session in the log so plays
+    /// labelled by [`label_sessions`] keep their numbers
+    pub fn next(&self) -> Self {
+        let mut seen_before = self.seen_before.clone();
+        seen_before.extend(self.plays.iter().map(|m| m.id));
+
+        let id = self.id.max(self.last_label) + 1;
+        Session {
+            id,
+            plays: Vec::new(),
+            seen_before,
+            last_label: id,
+        }
+    }
+
+    pub fn played(&mut self, map: &RcMap) {
+        self.plays.push(map.clone());
+    }
+
+    pub fn print_summary(&self) {
+        println!();
+        println!("Session {} Summary", self.id);
+        println!("  Rounds Played: {}", self.plays.len());
+
+        let modes: Vec<String> = Mode::ordered()
+            .iter()
+            .filter(|mode| self.plays.iter().any(|m| m.mode == **mode))
+            .map(Mode::to_string)
+            .collect();
+        println!("  Modes Covered: {}", modes.join(", "));
+
+        println!("  Maps Played:");
+        for map in &self.plays {
+            println!("    {}", map.map_info());
+        }
+
+        let mut first_time: Vec<&RcMap> = Vec::new();
+        for map in &self.plays {
+            if !self.seen_before.contains(&map.id) && !first_time.contains(&map) {
+                first_time.push(map);
+            }
+        }
+        println!("  First Time Ever: {}", first_time.len());
+        for map in first_time {
+            println!("    {}", map.map_info());
+        }
+
+        let gags = self.plays.iter().filter(|m| m.is_gag).count();
+        println!("  Gag Maps: {}", gags);
+        println!();
+    }
+}
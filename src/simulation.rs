@@ -0,0 +1,460 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+    thread,
+};
+
+use rand::random;
+
+use crate::map_data::{Groups, Maps, Mode, RcGroup, RcMap};
+use crate::map_scoring::{build_scores, candidate_weights, ScoringConfig, ScoringState};
+use crate::play_log::LogEntry;
+use crate::sampling::{pick_random_maps, Diversity, Sampler};
+
+/// How the simulated host chooses among the offered maps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickPolicy {
+    /// always the highest scored choice
+    Top,
+    /// any choice with equal chance
+    Uniform,
+    /// choices in proportion to their score
+    Weighted,
+}
+
+impl Display for PickPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PickPolicy::Top => "top",
+            PickPolicy::Uniform => "uniform",
+            PickPolicy::Weighted => "weighted",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown pick policy {0}")]
+pub struct UnknownPickPolicy(String);
+
+impl TryInto<PickPolicy> for &str {
+    type Error = UnknownPickPolicy;
+
+    fn try_into(self) -> Result<PickPolicy, Self::Error> {
+        match &*self.to_lowercase() {
+            "top" => Ok(PickPolicy::Top),
+            "uniform" => Ok(PickPolicy::Uniform),
+            "weighted" => Ok(PickPolicy::Weighted),
+            _ => Err(UnknownPickPolicy(self.to_string())),
+        }
+    }
+}
+
+impl PickPolicy {
+    fn pick<'a>(&self, choices: &'a [(f64, RcMap)]) -> Option<&'a RcMap> {
+        if choices.is_empty() {
+            return None;
+        }
+
+        let idx = match self {
+            PickPolicy::Top => 0,
+            PickPolicy::Uniform => (random::<f64>() * choices.len() as f64) as usize,
+            PickPolicy::Weighted => {
+                let sum: f64 = choices.iter().map(|c| c.0).sum();
+                let mut r = random::<f64>() * sum;
+                choices
+                    .iter()
+                    .position(|(s, _)| {
+                        r -= s;
+                        r <= 0.
+                    })
+                    .unwrap_or(choices.len() - 1)
+            }
+        };
+
+        choices.get(idx.min(choices.len() - 1)).map(|(_, m)| m)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub rounds: usize,
+    pub players: u16,
+    /// modes played in turn, repeating
+    pub rotation: Vec<Mode>,
+    pub policy: PickPolicy,
+    pub sampler: Sampler,
+    pub diversity: Diversity,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation {
+            rounds: 10_000,
+            players: 16,
+            rotation: Mode::ordered().to_vec(),
+            policy: PickPolicy::Top,
+            sampler: Sampler::Sequential,
+            diversity: Diversity::default(),
+        }
+    }
+}
+
+pub struct MapReport {
+    pub map: RcMap,
+    pub plays: u32,
+    /// share of the mode's rounds the map would get if picks followed its weight
+    pub expected: f64,
+    /// share of the mode's rounds the map actually got
+    pub observed: f64,
+    /// rounds between repeats of the map
+    pub mean_gap: Option<f64>,
+    pub min_gap: Option<usize>,
+    pub p10_gap: Option<usize>,
+    /// share of the map's plays that came right after a play from its group
+    pub same_group_rate: f64,
+}
+
+pub struct ModeReport {
+    pub mode: Mode,
+    pub rounds: u32,
+    /// 0 when every map is played equally, towards 1 when a few maps get all the plays
+    pub gini: f64,
+    /// entropy of the plays over the mode's maps, 1 when perfectly even
+    pub entropy: f64,
+}
+
+fn gini(counts: &[u32]) -> f64 {
+    let n = counts.len() as f64;
+    let sum: u32 = counts.iter().sum();
+    if counts.is_empty() || sum == 0 {
+        return 0.;
+    }
+
+    let mut sorted = counts.to_vec();
+    sorted.sort_unstable();
+    let weighted: f64 = sorted
+        .iter()
+        .zip(1..)
+        .map(|(c, i)| i as f64 * *c as f64)
+        .sum();
+
+    2. * weighted / (n * sum as f64) - (n + 1.) / n
+}
+
+fn normalized_entropy(counts: &[u32]) -> f64 {
+    let sum: u32 = counts.iter().sum();
+    if counts.len() < 2 || sum == 0 {
+        return 1.;
+    }
+
+    let h: f64 = counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / sum as f64;
+            -p * p.ln()
+        })
+        .sum();
+
+    h / (counts.len() as f64).ln()
+}
+
+impl Simulation {
+    pub fn run(
+        &self,
+        all_maps: &[RcMap],
+        config: &ScoringConfig,
+    ) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+        let mut state = ScoringState::new(all_maps, &config.params);
+
+        for mode in self.rotation.iter().cycle().take(self.rounds) {
+            let scores = build_scores(&state, *mode, self.players, all_maps, config);
+            let recent = self.diversity.recent(state.log());
+            let random_maps =
+                pick_random_maps(scores, self.sampler, &self.diversity, &recent, true)?;
+            if let Some(map) = self.policy.pick(&random_maps) {
+                state.push(LogEntry::new(map.clone(), None));
+            }
+        }
+
+        Ok(state.into_log())
+    }
+
+    pub fn report(
+        &self,
+        log: &[LogEntry],
+        all_groups: &[RcGroup],
+        all_maps: &[RcMap],
+        config: &ScoringConfig,
+    ) -> (Vec<MapReport>, Vec<ModeReport>) {
+        let mut rounds: HashMap<u16, Vec<usize>> = HashMap::new();
+        let mut after_sibling: HashMap<u16, u32> = HashMap::new();
+        for (round, entry) in log.iter().enumerate() {
+            rounds.entry(entry.map.id).or_default().push(round);
+            if round > 0 && log[round - 1].map.group() == entry.map.group() {
+                *after_sibling.entry(entry.map.id).or_default() += 1;
+            }
+        }
+
+        let mut groups = all_groups.to_vec();
+        groups.sort_by_key(|g| g.gid);
+
+        let mut maps = Vec::new();
+        let mut modes = Vec::new();
+        for mode in Mode::ordered() {
+            if !self.rotation.contains(&mode) {
+                continue;
+            }
+
+            let weights = candidate_weights(mode, self.players, all_maps, config);
+            let weight_sum: f64 = weights.values().sum();
+            let mode_rounds = log.iter().filter(|e| e.map.mode == mode).count() as u32;
+
+            let mut counts = Vec::new();
+            for map in groups.iter().flat_map(|g| &g.variants) {
+                let Some(weight) = weights.get(&map.id) else {
+                    continue;
+                };
+
+                let played = rounds.get(&map.id).map(Vec::as_slice).unwrap_or(&[]);
+                let mut gaps: Vec<usize> = played.windows(2).map(|w| w[1] - w[0]).collect();
+                gaps.sort_unstable();
+
+                let plays = played.len() as u32;
+                counts.push(plays);
+                maps.push(MapReport {
+                    map: map.clone(),
+                    plays,
+                    expected: weight / weight_sum,
+                    observed: if mode_rounds == 0 {
+                        0.
+                    } else {
+                        plays as f64 / mode_rounds as f64
+                    },
+                    mean_gap: (!gaps.is_empty())
+                        .then(|| gaps.iter().sum::<usize>() as f64 / gaps.len() as f64),
+                    min_gap: gaps.first().copied(),
+                    p10_gap: (!gaps.is_empty()).then(|| gaps[(gaps.len() - 1) / 10]),
+                    same_group_rate: if plays == 0 {
+                        0.
+                    } else {
+                        *after_sibling.get(&map.id).unwrap_or(&0) as f64 / plays as f64
+                    },
+                });
+            }
+
+            modes.push(ModeReport {
+                mode,
+                rounds: mode_rounds,
+                gini: gini(&counts),
+                entropy: normalized_entropy(&counts),
+            });
+        }
+
+        (maps, modes)
+    }
+}
+
+fn opt<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+pub fn print_report(maps: &[MapReport], modes: &[ModeReport]) {
+    println!("\"mode\",\"nickname\",\"plays\",\"expected\",\"observed\",\"mean_gap\",\"min_gap\",\"p10_gap\",\"same_group_rate\"");
+    for m in maps {
+        println!(
+            "\"{}\",\"{}\",{},{:.4},{:.4},{},{},{},{:.4}",
+            m.map.mode.name(),
+            m.map.nickname,
+            m.plays,
+            m.expected,
+            m.observed,
+            opt(m.mean_gap.map(|g| format!("{:.2}", g))),
+            opt(m.min_gap),
+            opt(m.p10_gap),
+            m.same_group_rate,
+        );
+    }
+
+    println!();
+    println!("\"mode\",\"rounds\",\"gini\",\"entropy\"");
+    for m in modes {
+        println!(
+            "\"{}\",{},{:.4},{:.4}",
+            m.mode.name(),
+            m.rounds,
+            m.gini,
+            m.entropy
+        );
+    }
+}
+
+/// The numbers of a [`MapReport`], without the map so they can cross threads
+pub struct MapMetrics {
+    pub id: u16,
+    pub expected: f64,
+    pub observed: f64,
+    pub mean_gap: Option<f64>,
+    pub min_gap: Option<usize>,
+    pub p10_gap: Option<usize>,
+    pub same_group_rate: f64,
+}
+
+impl From<&MapReport> for MapMetrics {
+    fn from(r: &MapReport) -> Self {
+        MapMetrics {
+            id: r.map.id,
+            expected: r.expected,
+            observed: r.observed,
+            mean_gap: r.mean_gap,
+            min_gap: r.min_gap,
+            p10_gap: r.p10_gap,
+            same_group_rate: r.same_group_rate,
+        }
+    }
+}
+
+pub type TrialResult = (Vec<MapMetrics>, Vec<ModeReport>);
+
+impl Simulation {
+    /// Run independent trials spread over `threads` threads.
+    ///
+    /// Maps are reference counted and can't be shared between threads, so every thread loads its
+    /// own copy of the catalog with `load`.
+    pub fn run_trials<L>(
+        &self,
+        trials: usize,
+        threads: usize,
+        config: &ScoringConfig,
+        load: L,
+    ) -> Result<Vec<TrialResult>, String>
+    where
+        L: Fn() -> Result<(Groups, Maps), Box<dyn Error>> + Sync,
+    {
+        let next = AtomicUsize::new(0);
+
+        let worker = || -> Result<Vec<TrialResult>, String> {
+            let (groups, maps) = load().map_err(|e| e.to_string())?;
+            let groups: Vec<RcGroup> = groups.into_values().collect();
+            let all_maps: Vec<RcMap> = maps.into_values().collect();
+
+            let mut results = Vec::new();
+            while next.fetch_add(1, AtomicOrdering::Relaxed) < trials {
+                let log = self.run(&all_maps, config).map_err(|e| e.to_string())?;
+                let (maps, modes) = self.report(&log, &groups, &all_maps, config);
+                results.push((maps.iter().map(MapMetrics::from).collect(), modes));
+            }
+            Ok(results)
+        };
+
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..threads.clamp(1, trials.max(1)))
+                .map(|_| s.spawn(worker))
+                .collect();
+
+            let mut results = Vec::new();
+            for h in handles {
+                results.extend(h.join().map_err(|_| "simulation thread panicked")??);
+            }
+            Ok(results)
+        })
+    }
+}
+
+/// Mean and the half width of its 95% confidence interval
+struct Estimate(f64, f64);
+
+impl Estimate {
+    fn of<I>(values: I) -> Option<Self>
+    where
+        I: Iterator<Item = f64>,
+    {
+        let values: Vec<f64> = values.collect();
+        if values.is_empty() {
+            return None;
+        }
+
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let ci = if values.len() < 2 {
+            0.
+        } else {
+            let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.);
+            1.96 * (var / n).sqrt()
+        };
+
+        Some(Estimate(mean, ci))
+    }
+}
+
+impl Display for Estimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.4},{:.4}", self.0, self.1)
+    }
+}
+
+fn est(e: Option<Estimate>) -> String {
+    e.map(|e| e.to_string()).unwrap_or_else(|| ",".to_string())
+}
+
+/// Print the mean of each metric over the trials with its 95% confidence interval (`_ci`)
+pub fn print_aggregate(results: &[TrialResult], all_groups: &[RcGroup]) {
+    let mut groups = all_groups.to_vec();
+    groups.sort_by_key(|g| g.gid);
+
+    println!("{} trials", results.len());
+    println!("\"mode\",\"nickname\",\"expected\",\"observed\",\"observed_ci\",\"mean_gap\",\"mean_gap_ci\",\"min_gap\",\"min_gap_ci\",\"p10_gap\",\"p10_gap_ci\",\"same_group_rate\",\"same_group_rate_ci\"");
+    for mode in Mode::ordered() {
+        for map in groups.iter().flat_map(|g| &g.variants) {
+            if map.mode != mode {
+                continue;
+            }
+
+            let metrics: Vec<&MapMetrics> = results
+                .iter()
+                .flat_map(|(maps, _)| maps.iter().filter(|m| m.id == map.id))
+                .collect();
+            let Some(first) = metrics.first() else {
+                continue;
+            };
+
+            println!(
+                "\"{}\",\"{}\",{:.4},{},{},{},{},{}",
+                mode.name(),
+                map.nickname,
+                first.expected,
+                est(Estimate::of(metrics.iter().map(|m| m.observed))),
+                est(Estimate::of(metrics.iter().filter_map(|m| m.mean_gap))),
+                est(Estimate::of(
+                    metrics.iter().filter_map(|m| m.min_gap.map(|g| g as f64))
+                )),
+                est(Estimate::of(
+                    metrics.iter().filter_map(|m| m.p10_gap.map(|g| g as f64))
+                )),
+                est(Estimate::of(metrics.iter().map(|m| m.same_group_rate))),
+            );
+        }
+    }
+
+    println!();
+    println!("\"mode\",\"gini\",\"gini_ci\",\"entropy\",\"entropy_ci\"");
+    for mode in Mode::ordered() {
+        let modes: Vec<&ModeReport> = results
+            .iter()
+            .flat_map(|(_, modes)| modes.iter().filter(|m| m.mode == mode))
+            .collect();
+        if modes.is_empty() {
+            continue;
+        }
+
+        println!(
+            "\"{}\",{},{}",
+            mode.name(),
+            est(Estimate::of(modes.iter().map(|m| m.gini))),
+            est(Estimate::of(modes.iter().map(|m| m.entropy))),
+        );
+    }
+}
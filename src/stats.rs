@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+
+use crate::map_data::{Mode, RcGroup, RcMap};
+use crate::play_log::LogEntry;
+
+pub struct MapStats {
+    pub map: RcMap,
+    pub plays: u32,
+    /// rounds between consecutive plays of the map
+    pub avg_gap: Option<f64>,
+    pub max_gap: Option<usize>,
+    pub last_played: Option<DateTime<Utc>>,
+}
+
+pub struct Stats {
+    pub total: u32,
+    pub maps: Vec<MapStats>,
+    pub groups: Vec<(RcGroup, u32)>,
+    pub modes: Vec<(Mode, u32)>,
+    /// plays per player-count tier of the maps played
+    pub tiers: Vec<(u16, u32)>,
+}
+
+impl Stats {
+    pub fn compute(log: &[LogEntry], all_groups: &[RcGroup]) -> Self {
+        let mut rounds: BTreeMap<u16, Vec<usize>> = BTreeMap::new();
+        let mut last_played: BTreeMap<u16, DateTime<Utc>> = BTreeMap::new();
+        let mut tiers: BTreeMap<u16, u32> = BTreeMap::new();
+
+        for (round, entry) in log.iter().enumerate() {
+            rounds.entry(entry.map.id).or_default().push(round);
+            if let Some(t) = entry.time {
+                last_played.insert(entry.map.id, t);
+            }
+            *tiers.entry(entry.map.players).or_default() += 1;
+        }
+
+        let mut groups: Vec<RcGroup> = all_groups.to_vec();
+        groups.sort_by_key(|g| g.gid);
+
+        let mut maps = Vec::new();
+        let mut group_counts = Vec::new();
+        for group in &groups {
+            let mut group_plays = 0;
+            for map in &group.variants {
+                let played = rounds.get(&map.id).map(Vec::as_slice).unwrap_or(&[]);
+                let gaps: Vec<usize> = played.windows(2).map(|w| w[1] - w[0]).collect();
+
+                group_plays += played.len() as u32;
+                maps.push(MapStats {
+                    map: map.clone(),
+                    plays: played.len() as u32,
+                    avg_gap: (!gaps.is_empty())
+                        .then(|| gaps.iter().sum::<usize>() as f64 / gaps.len() as f64),
+                    max_gap: gaps.iter().max().copied(),
+                    last_played: last_played.get(&map.id).copied(),
+                });
+            }
+            group_counts.push((group.clone(), group_plays));
+        }
+
+        let modes = Mode::ordered()
+            .into_iter()
+            .map(|mode| {
+                let plays = log.iter().filter(|e| e.map.mode == mode).count() as u32;
+                (mode, plays)
+            })
+            .collect();
+
+        Stats {
+            total: log.len() as u32,
+            maps,
+            groups: group_counts,
+            modes,
+            tiers: tiers.into_iter().collect(),
+        }
+    }
+
+    pub fn never_played(&self) -> impl Iterator<Item = &MapStats> {
+        self.maps.iter().filter(|m| m.plays == 0)
+    }
+
+    fn share(&self, plays: u32) -> f64 {
+        if self.total == 0 {
+            0.
+        } else {
+            plays as f64 / self.total as f64
+        }
+    }
+
+    pub fn print_table(&self) {
+        println!("{} plays", self.total);
+
+        println!();
+        println!(
+            "{:<28} {:<8} {:>3} {:>5} {:>8} {:>8}  Last Played",
+            "Map", "Mode", "Pl", "Plays", "Avg Gap", "Max Gap"
+        );
+        for m in self.maps.iter().filter(|m| m.plays > 0) {
+            println!(
+                "{:<28} {:<8} {:>3} {:>5} {:>8} {:>8}  {}",
+                m.map.nickname,
+                m.map.mode.name(),
+                m.map.players,
+                m.plays,
+                m.avg_gap.map(|g| format!("{:.1}", g)).unwrap_or_default(),
+                m.max_gap.map(|g| g.to_string()).unwrap_or_default(),
+                date(m.last_played),
+            );
+        }
+
+        println!();
+        println!("{:<28} {:>5}", "Group", "Plays");
+        for (group, plays) in &self.groups {
+            println!("{:<28} {:>5}", group.basename, plays);
+        }
+
+        println!();
+        println!("{:<28} {:>5} {:>7}", "Mode", "Plays", "Share");
+        for (mode, plays) in &self.modes {
+            println!(
+                "{:<28} {:>5} {:>6.2}%",
+                mode.name(),
+                plays,
+                self.share(*plays) * 100.
+            );
+        }
+
+        println!();
+        println!("{:<28} {:>5} {:>7}", "Players", "Plays", "Share");
+        for (players, plays) in &self.tiers {
+            println!(
+                "{:<28} {:>5} {:>6.2}%",
+                players,
+                plays,
+                self.share(*plays) * 100.
+            );
+        }
+
+        println!();
+        println!("Never Played");
+        for m in self.never_played() {
+            println!(
+                "  {} {} ({})",
+                m.map.nickname,
+                m.map.mode.name(),
+                m.map.players
+            );
+        }
+    }
+
+    /// One CSV table per section, separated by blank lines
+    pub fn to_csv(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("id,nickname,group,mode,players,plays,avg_gap,max_gap,last_played\n");
+        for m in &self.maps {
+            out.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{}\n",
+                m.map.id,
+                csv_field(&m.map.nickname),
+                csv_field(&m.map.group().basename),
+                m.map.mode.name(),
+                m.map.players,
+                m.plays,
+                m.avg_gap.map(|g| format!("{:.2}", g)).unwrap_or_default(),
+                m.max_gap.map(|g| g.to_string()).unwrap_or_default(),
+                date(m.last_played),
+            ));
+        }
+
+        out.push_str("\ngid,group,plays\n");
+        for (group, plays) in &self.groups {
+            out.push_str(&format!(
+                "{},{},{}\n",
+                group.gid,
+                csv_field(&group.basename),
+                plays
+            ));
+        }
+
+        out.push_str("\nmode,plays,share\n");
+        for (mode, plays) in &self.modes {
+            out.push_str(&format!(
+                "{},{},{:.4}\n",
+                mode.name(),
+                plays,
+                self.share(*plays)
+            ));
+        }
+
+        out.push_str("\nplayers,plays,share\n");
+        for (players, plays) in &self.tiers {
+            out.push_str(&format!(
+                "{},{},{:.4}\n",
+                players,
+                plays,
+                self.share(*plays)
+            ));
+        }
+
+        out
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let maps: Vec<JsonValue> = self
+            .maps
+            .iter()
+            .map(|m| {
+                object! {
+                    id: m.map.id,
+                    nickname: m.map.nickname.as_str(),
+                    gid: m.map.group().gid,
+                    mode: m.map.mode.name(),
+                    players: m.map.players,
+                    plays: m.plays,
+                    avg_gap: m.avg_gap,
+                    max_gap: m.max_gap,
+                    last_played: m.last_played.map(|t| t.to_rfc3339()),
+                }
+            })
+            .collect();
+        let groups: Vec<JsonValue> = self
+            .groups
+            .iter()
+            .map(|(g, plays)| object! { gid: g.gid, name: g.basename.as_str(), plays: *plays })
+            .collect();
+        let modes: Vec<JsonValue> = self
+            .modes
+            .iter()
+            .map(|(mode, plays)| {
+                object! { mode: mode.name(), plays: *plays, share: self.share(*plays) }
+            })
+            .collect();
+        let tiers: Vec<JsonValue> = self
+            .tiers
+            .iter()
+            .map(|(players, plays)| {
+                object! { players: *players, plays: *plays, share: self.share(*plays) }
+            })
+            .collect();
+        let never_played: Vec<JsonValue> = self.never_played().map(|m| m.map.id.into()).collect();
+
+        object! {
+            total: self.total,
+            maps: maps,
+            groups: groups,
+            modes: modes,
+            tiers: tiers,
+            never_played: never_played,
+        }
+    }
+}
+
+fn date(t: Option<DateTime<Utc>>) -> String {
+    t.map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
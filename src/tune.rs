@@ -0,0 +1,172 @@
+use std::error::Error;
+
+use json::object;
+use rand::random;
+
+use crate::map_data::{RcGroup, RcMap};
+use crate::map_scoring::{ScoringConfig, ScoringParams};
+use crate::simulation::{MapReport, ModeReport, Simulation};
+
+// ranges searched, discounts are searched by the number of rounds it takes to halve a penalty
+static ROUND_HALF_LIVES: [f64; 3] = [32., 64., 128.];
+static CROSS_TYPE_HALF_LIVES: [f64; 3] = [6., 12., 24.];
+static PENALTY_NONLINEARITIES: [f64; 3] = [1.0, 1.4, 1.8];
+static AGE_POWS: [f64; 3] = [0.2, 0.4, 0.8];
+
+/// How a set of scoring parameters played out in simulation
+pub struct Trial {
+    pub params: ScoringParams,
+    /// mean Gini coefficient of plays within each mode, lower is more uniform
+    pub gini: f64,
+    /// mean over modes of the shortest gap before any map repeats, higher is better
+    pub min_gap: f64,
+    /// share of plays right after a play from the same group, lower is better
+    pub sibling_rate: f64,
+}
+
+impl Trial {
+    fn new(params: ScoringParams, maps: &[MapReport], modes: &[ModeReport]) -> Self {
+        let gini = modes.iter().map(|m| m.gini).sum::<f64>() / modes.len().max(1) as f64;
+
+        let min_gaps: Vec<usize> = modes
+            .iter()
+            .filter_map(|mode| {
+                maps.iter()
+                    .filter(|m| m.map.mode == mode.mode)
+                    .filter_map(|m| m.min_gap)
+                    .min()
+            })
+            .collect();
+        let min_gap = min_gaps.iter().sum::<usize>() as f64 / min_gaps.len().max(1) as f64;
+
+        let plays: u32 = maps.iter().map(|m| m.plays).sum();
+        let after_sibling: f64 = maps
+            .iter()
+            .map(|m| m.same_group_rate * m.plays as f64)
+            .sum();
+        let sibling_rate = after_sibling / plays.max(1) as f64;
+
+        Trial {
+            params,
+            gini,
+            min_gap,
+            sibling_rate,
+        }
+    }
+
+    /// at least as good on every objective and better on one
+    fn dominates(&self, o: &Trial) -> bool {
+        let no_worse =
+            self.gini <= o.gini && self.min_gap >= o.min_gap && self.sibling_rate <= o.sibling_rate;
+        let better =
+            self.gini < o.gini || self.min_gap > o.min_gap || self.sibling_rate < o.sibling_rate;
+        no_worse && better
+    }
+}
+
+fn grid(base: &ScoringParams) -> Vec<ScoringParams> {
+    let mut params = Vec::new();
+    for round in ROUND_HALF_LIVES {
+        for cross in CROSS_TYPE_HALF_LIVES {
+            for penalty_nonlinearity in PENALTY_NONLINEARITIES {
+                for age_pow in AGE_POWS {
+                    params.push(ScoringParams {
+                        round_discount: ScoringParams::discount_for_half_life(round),
+                        cross_type_round_discount: ScoringParams::discount_for_half_life(cross),
+                        penalty_nonlinearity,
+                        age_pow,
+                        ..*base
+                    });
+                }
+            }
+        }
+    }
+    params
+}
+
+/// a random point within the bounds of the grid, half lives drawn evenly on a log scale
+fn random_params(base: &ScoringParams) -> ScoringParams {
+    let between = |lo: f64, hi: f64| lo + random::<f64>() * (hi - lo);
+    let log_between = |lo: f64, hi: f64| between(lo.ln(), hi.ln()).exp();
+
+    ScoringParams {
+        round_discount: ScoringParams::discount_for_half_life(log_between(32., 128.)),
+        cross_type_round_discount: ScoringParams::discount_for_half_life(log_between(6., 24.)),
+        penalty_nonlinearity: between(1.0, 1.8),
+        age_pow: between(0.2, 0.8),
+        ..*base
+    }
+}
+
+/// Simulate every parameter set, the current parameters first, then a grid or `samples` random sets
+pub fn tune(
+    simulation: &Simulation,
+    all_groups: &[RcGroup],
+    all_maps: &[RcMap],
+    config: &mut ScoringConfig,
+    samples: Option<usize>,
+) -> Result<Vec<Trial>, Box<dyn Error>> {
+    let base = config.params;
+    let mut candidates = vec![base];
+    match samples {
+        Some(n) => candidates.extend((0..n).map(|_| random_params(&base))),
+        None => candidates.extend(grid(&base)),
+    }
+
+    let total = candidates.len();
+    let mut trials = Vec::new();
+    for (params, idx) in candidates.into_iter().zip(1..) {
+        eprint!("\rSimulating {}/{}", idx, total);
+        config.params = params;
+        let log = simulation.run(all_maps, config)?;
+        let (maps, modes) = simulation.report(&log, all_groups, all_maps, config);
+        trials.push(Trial::new(params, &maps, &modes));
+    }
+    eprintln!();
+
+    Ok(trials)
+}
+
+pub fn print_trials(trials: &[Trial]) {
+    let pareto: Vec<bool> = trials
+        .iter()
+        .map(|t| !trials.iter().any(|o| o.dominates(t)))
+        .collect();
+
+    println!(
+        "  {:>4} {:>10} {:>10} {:>8} {:>8} | {:>8} {:>8} {:>8}",
+        "#", "half-life", "cross h-l", "nonlin", "age pow", "gini", "min gap", "sibling"
+    );
+    for (idx, t) in trials.iter().enumerate() {
+        let p = &t.params;
+        println!(
+            "{} {:>4} {:>10.1} {:>10.1} {:>8.3} {:>8.3} | {:>8.4} {:>8.1} {:>8.4}",
+            if pareto[idx] { '*' } else { ' ' },
+            idx,
+            ScoringParams::half_life(p.round_discount),
+            ScoringParams::half_life(p.cross_type_round_discount),
+            p.penalty_nonlinearity,
+            p.age_pow,
+            t.gini,
+            t.min_gap,
+            t.sibling_rate,
+        );
+    }
+    println!("(#0 is the current scoring, * marks the Pareto-best)");
+
+    println!();
+    println!("Pareto-best scoring sections, for a profile file:");
+    for (t, idx) in trials.iter().zip(0..).filter(|(_, idx)| pareto[*idx]) {
+        let p = &t.params;
+        let scoring = object! {
+            scoring: {
+                round_discount: p.round_discount,
+                cross_type_round_discount: p.cross_type_round_discount,
+                penalty_nonlinearity: p.penalty_nonlinearity,
+                age_pow: p.age_pow,
+                similar_group_factor: p.similar_group_factor,
+            }
+        };
+        println!("#{} {}", idx, scoring.dump());
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteMethod {
+    Plurality,
+    InstantRunoff,
+    Borda,
+}
+
+impl Display for VoteMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoteMethod::Plurality => "plurality",
+            VoteMethod::InstantRunoff => "irv",
+            VoteMethod::Borda => "borda",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown vote method {0}")]
+pub struct UnknownVoteMethod(String);
+
+impl TryInto<VoteMethod> for &str {
+    type Error = UnknownVoteMethod;
+
+    fn try_into(self) -> Result<VoteMethod, Self::Error> {
+        match &*self.to_lowercase() {
+            "plurality" => Ok(VoteMethod::Plurality),
+            "irv" | "instant-runoff" => Ok(VoteMethod::InstantRunoff),
+            "borda" => Ok(VoteMethod::Borda),
+            _ => Err(UnknownVoteMethod(self.to_string())),
+        }
+    }
+}
+
+/// One player's vote over the offered choices, as indexes into the choices
+#[derive(Debug, Default)]
+pub struct Ballot {
+    /// most preferred first, choices left out are ranked below all listed ones
+    pub ranking: Vec<usize>,
+    pub vetoes: Vec<usize>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BallotError {
+    #[error("'{0}' is not a choice number")]
+    NotAChoice(String),
+    #[error("choice {0} out of range 1..{1}")]
+    OutOfRange(usize, usize),
+    #[error("choice {0} appears more than once")]
+    Repeated(usize),
+}
+
+impl Ballot {
+    /// Parse a ballot like `2 1 !3`: choice numbers in order of preference, `!` marks a veto
+    pub fn parse(line: &str, choice_ct: usize) -> Result<Self, BallotError> {
+        let mut ballot = Ballot::default();
+
+        for word in line.split_whitespace() {
+            let (veto, num) = match word.strip_prefix('!') {
+                Some(num) => (true, num),
+                None => (false, word),
+            };
+            let n = num
+                .parse::<usize>()
+                .map_err(|_| BallotError::NotAChoice(word.to_string()))?;
+            if n == 0 || n > choice_ct {
+                return Err(BallotError::OutOfRange(n, choice_ct));
+            }
+            let idx = n - 1;
+            if ballot.ranking.contains(&idx) || ballot.vetoes.contains(&idx) {
+                return Err(BallotError::Repeated(n));
+            }
+
+            if veto {
+                ballot.vetoes.push(idx);
+            } else {
+                ballot.ranking.push(idx);
+            }
+        }
+
+        Ok(ballot)
+    }
+
+    /// this ballot's most preferred choice that is still standing
+    fn top(&self, standing: &[bool]) -> Option<usize> {
+        self.ranking.iter().copied().find(|c| standing[*c])
+    }
+}
+
+pub struct Tally {
+    pub winner: usize,
+    /// votes (or Borda points) per choice in the deciding round
+    pub totals: Vec<u32>,
+    /// choices knocked out by a majority veto
+    pub vetoed: Vec<bool>,
+}
+
+/// Pick the highest total among standing choices, ties going to the higher score
+fn best(totals: &[u32], standing: &[bool], scores: &[f64]) -> usize {
+    (0..totals.len())
+        .filter(|c| standing[*c])
+        .max_by(|a, b| {
+            totals[*a]
+                .cmp(&totals[*b])
+                .then(scores[*a].partial_cmp(&scores[*b]).unwrap())
+        })
+        .unwrap()
+}
+
+fn first_preferences(ballots: &[Ballot], standing: &[bool]) -> Vec<u32> {
+    let mut totals = vec![0; standing.len()];
+    for c in ballots.iter().filter_map(|b| b.top(standing)) {
+        totals[c] += 1;
+    }
+    totals
+}
+
+/// Tally the ballots over choices with the given scores.
+///
+/// A choice vetoed by more than half the ballots is out, unless that would rule out every choice.
+pub fn tally(method: VoteMethod, scores: &[f64], ballots: &[Ballot]) -> Tally {
+    assert!(!scores.is_empty());
+    let choice_ct = scores.len();
+
+    let mut vetoed = vec![false; choice_ct];
+    for (c, v) in vetoed.iter_mut().enumerate() {
+        let vetoes = ballots.iter().filter(|b| b.vetoes.contains(&c)).count();
+        *v = vetoes * 2 > ballots.len();
+    }
+    if vetoed.iter().all(|v| *v) {
+        vetoed = vec![false; choice_ct];
+    }
+
+    let mut standing: Vec<bool> = vetoed.iter().map(|v| !v).collect();
+
+    let totals = match method {
+        VoteMethod::Plurality => first_preferences(ballots, &standing),
+        VoteMethod::InstantRunoff => loop {
+            let totals = first_preferences(ballots, &standing);
+            let cast: u32 = totals.iter().sum();
+            let leader = best(&totals, &standing, scores);
+            let remaining = standing.iter().filter(|s| **s).count();
+            if totals[leader] * 2 > cast || remaining == 1 {
+                break totals;
+            }
+
+            // knock out the weakest choice, ties going against the lower score
+            let loser = (0..choice_ct)
+                .filter(|c| standing[*c])
+                .min_by(|a, b| {
+                    totals[*a]
+                        .cmp(&totals[*b])
+                        .then(scores[*a].partial_cmp(&scores[*b]).unwrap())
+                })
+                .unwrap();
+            standing[loser] = false;
+        },
+        VoteMethod::Borda => {
+            let mut totals = vec![0; choice_ct];
+            let standing_ct = standing.iter().filter(|s| **s).count() as u32;
+            for b in ballots {
+                let ranked = b.ranking.iter().filter(|c| standing[**c]);
+                for (c, points) in ranked.zip((0..standing_ct).rev()) {
+                    totals[*c] += points;
+                }
+            }
+            totals
+        }
+    };
+
+    Tally {
+        winner: best(&totals, &standing, scores),
+        totals,
+        vetoed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ballots(lines: &[&str], choice_ct: usize) -> Vec<Ballot> {
+        lines
+            .iter()
+            .map(|l| Ballot::parse(l, choice_ct).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn plurality_breaks_ties_by_score() {
+        let b = ballots(&["1", "2", "2"], 3);
+        let t = tally(VoteMethod::Plurality, &[0.5, 0.3, 0.2], &b);
+        assert_eq!((t.winner, t.totals), (1, vec![1, 2, 0]));
+
+        let b = ballots(&["1", "2"], 3);
+        assert_eq!(tally(VoteMethod::Plurality, &[0.5, 0.3, 0.2], &b).winner, 0);
+        assert_eq!(tally(VoteMethod::Plurality, &[0.2, 0.5, 0.3], &b).winner, 1);
+    }
+
+    #[test]
+    fn instant_runoff_transfers_eliminated_votes() {
+        // first preferences 2-1-2, choice 2 goes out and its vote moves to choice 1
+        let b = ballots(&["1 2", "1 2", "2 1", "3 2", "3 2"], 3);
+        let scores = [0.2, 0.3, 0.5];
+
+        let t = tally(VoteMethod::InstantRunoff, &scores, &b);
+        assert_eq!((t.winner, t.totals), (0, vec![3, 0, 2]));
+
+        // where plurality has a tie, taken by the higher score
+        assert_eq!(tally(VoteMethod::Plurality, &scores, &b).winner, 2);
+    }
+
+    #[test]
+    fn borda_counts_points_over_standing_choices() {
+        let b = ballots(&["1 2 3", "2 3 1", "2 1 3"], 3);
+        let t = tally(VoteMethod::Borda, &[0.4, 0.3, 0.3], &b);
+        assert_eq!((t.winner, t.totals), (1, vec![3, 5, 1]));
+
+        // choice 3 is vetoed, so the others get 1 and 0 points
+        let b = ballots(&["1 2 !3", "2 1 !3", "2 3"], 3);
+        let t = tally(VoteMethod::Borda, &[0.4, 0.3, 0.3], &b);
+        assert_eq!(t.vetoed, vec![false, false, true]);
+        assert_eq!((t.winner, t.totals), (1, vec![1, 2, 0]));
+    }
+
+    #[test]
+    fn majority_veto_rules_a_choice_out() {
+        // half the ballots isn't a majority
+        let b = ballots(&["!1 2", "1"], 3);
+        let t = tally(VoteMethod::Plurality, &[0.5, 0.3, 0.2], &b);
+        assert_eq!(t.vetoed, vec![false; 3]);
+        assert_eq!(t.winner, 0);
+
+        let b = ballots(&["!1 2", "!1 2", "1"], 3);
+        let t = tally(VoteMethod::Plurality, &[0.5, 0.3, 0.2], &b);
+        assert_eq!(t.vetoed, vec![true, false, false]);
+        assert_eq!((t.winner, t.totals), (1, vec![0, 2, 0]));
+    }
+
+    #[test]
+    fn vetoing_everything_vetoes_nothing() {
+        let b = ballots(&["!1 !2", "!2 !1"], 2);
+        for method in [
+            VoteMethod::Plurality,
+            VoteMethod::InstantRunoff,
+            VoteMethod::Borda,
+        ] {
+            let t = tally(method, &[0.4, 0.6], &b);
+            assert_eq!(t.vetoed, vec![false, false]);
+            assert_eq!(t.winner, 1, "{}", method);
+        }
+    }
+}